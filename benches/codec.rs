@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use quic::config::GameConfig;
+use quic::game::{GameState, InputCommand, Player, PlayerInput, Simulation, Snapshot};
+use quic::protocol::{Codec, ServerMessage};
+
+// a state that has been running for a while, with `players` players moving around
+fn realistic_state(players: usize) -> GameState {
+    let mut simulation = Simulation::new(7, &GameConfig::default());
+    let mut state = GameState::new();
+    for id in 0..players {
+        state.players.insert(id, Player::spawn());
+    }
+
+    for seq in 1..=120 {
+        let inputs: Vec<_> = (0..players)
+            .map(|player| {
+                let command = if player % 2 == 0 { InputCommand::MoveLeft } else { InputCommand::MoveRight };
+                PlayerInput { player, seq, command }
+            })
+            .collect();
+        simulation.step(&mut state, &inputs, Duration::from_millis(16));
+    }
+    state
+}
+
+fn state_message(players: usize) -> ServerMessage {
+    ServerMessage::State { player_id: 0, snapshot: Snapshot::Full(realistic_state(players)) }
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode_state");
+    for players in [1, 8, 32] {
+        let message = state_message(players);
+        for codec in Codec::SUPPORTED {
+            // criterion only reports time, print the size next to it
+            println!("{:?} with {} players: {} bytes", codec, players, codec.encode(&message).len());
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", codec), players), &message, |b, message| {
+                b.iter(|| codec.encode(black_box(message)))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_state");
+    for players in [1, 8, 32] {
+        let message = state_message(players);
+        for codec in Codec::SUPPORTED {
+            let bytes = codec.encode(&message);
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", codec), players), &bytes, |b, bytes| {
+                b.iter(|| codec.decode::<ServerMessage>(black_box(bytes)).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, decode);
+criterion_main!(benches);
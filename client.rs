@@ -1,95 +1,165 @@
-use tokio::sync::Mutex;
-use std::{io::{self, Write}, sync::{atomic::{AtomicBool, Ordering}, Arc}};
-use quic_client::QuicClient;
-mod quic_client;
-
-use serde::{Serialize, Deserialize};
-use crossterm::{
-    event::{self, Event, KeyCode},
-    terminal::{disable_raw_mode, enable_raw_mode},
-};
-
-
-#[derive(Debug, Serialize, Deserialize)]
-pub enum InputCommand {
-    MoveLeft,
-    MoveRight,
-    None,
-}
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Player {
-    pub x: usize,
-    pub y: usize,
-    pub hp: u32,
-    pub score : usize
-}
+use quic::game::{InputCommand, Interpolator, Predictor, Role, Snapshot};
+use quic::protocol::{ClientMessage, Codec, GameEvent, ServerMessage, MAX_CHAT_LEN};
+use quic::config::TransportSettings;
+use quic::quic_client::{Backoff, ClientIdentity, JoinRequest, Joined, QuicClient, ServerLink, ServerTrust};
+use quic::quic_server;
+use quic::render::{Renderer, TerminalRenderer};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Enemy {
-    pub x: usize,
-    pub y: usize,
-}
+use clap::Parser;
+use quinn::{ConnectionError, VarInt};
+use tokio::time::MissedTickBehavior;
 
+use crossterm::event::{self, Event, KeyCode};
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GameState {
-    pub player: Player,
-    pub enemies : Vec<Enemy>,
-    pub game_over: bool, 
-    pub message : String
+// frames drawn per second, independent of the server tick rate
+const FRAME_RATE: u32 = 30;
+// how long a notice from the server stays on screen
+const NOTICE_TIME: Duration = Duration::from_secs(3);
+// reconnect attempts wait this long at first, doubling up to the max
+const RECONNECT_FIRST_DELAY: Duration = Duration::from_millis(250);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(5);
+
+// everything defaults to a server started with no flags on this machine
+#[derive(Parser)]
+#[command(about = "QUIC runner game client")]
+struct Args {
+    /// server address, host:port
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    server: String,
+    /// name the server's certificate was issued for
+    #[arg(long, default_value = "localhost")]
+    server_name: String,
+    /// PEM file with the certificate(s) to trust, the server's cert.pem works
+    #[arg(long, default_value = "cert.pem")]
+    ca: PathBuf,
+    /// client certificate (PEM) for servers that require mTLS, see `server issue-client`
+    #[arg(long, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+    /// private key (PEM) for --client-cert
+    #[arg(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// player name shown to others, defaults to $USER (mTLS servers use the certificate's name)
+    #[arg(long)]
+    name: Option<String>,
+    /// trust only a server certificate with this SHA-256 fingerprint (the server prints it)
+    #[arg(long, conflicts_with_all = ["tofu", "insecure_dev"])]
+    fingerprint: Option<String>,
+    /// trust the server's certificate on first use and remember it in --known-hosts
+    #[arg(long, conflicts_with = "insecure_dev")]
+    tofu: bool,
+    /// file of "<server> <fingerprint>" lines used by --tofu
+    #[arg(long, default_value = "known_hosts")]
+    known_hosts: PathBuf,
+    /// accept any server certificate, never use this outside local development
+    #[arg(long)]
+    insecure_dev: bool,
+    /// only offer this codec (binary or json), json makes the traffic readable when debugging
+    #[arg(long)]
+    codec: Option<Codec>,
+    /// host the game on a server started with --admin-token
+    #[arg(long, env = "QUIC_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+    /// added delay each way in milliseconds, to see prediction at work
+    #[arg(long, default_value_t = 0)]
+    latency_ms: u64,
 }
 
-/*
-Map Example
-13x5 grid
- ___________
-|           |
-|  E        |
-|     E F   |
-|  F        |
-|     ^     |
- -----------
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
 
-forward is like 500ms
-left right is like 100ms
+    let server_addr = tokio::net::lookup_host(&args.server)
+        .await
+        .map_err(|e| format!("Failed to resolve server address '{}': {}", args.server, e))?
+        .next()
+        .ok_or_else(|| format!("No addresses found for '{}'", args.server))?;
+    let trust = if let Some(fingerprint) = &args.fingerprint {
+        ServerTrust::Fingerprint(fingerprint.clone())
+    } else if args.tofu {
+        ServerTrust::KnownHosts { path: args.known_hosts.clone(), host: args.server.clone() }
+    } else if args.insecure_dev {
+        println!("Warning: --insecure-dev accepts any server certificate");
+        ServerTrust::InsecureDev
+    } else {
+        ServerTrust::Ca(args.ca.clone())
+    };
 
-*/
+    let identity = match (&args.client_cert, &args.client_key) {
+        (Some(cert), Some(key)) => Some(ClientIdentity { cert: cert.clone(), key: key.clone() }),
+        _ => None,
+    };
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let mut client = quic_client::QuicClient::new();
+    let target = Target {
+        addr: server_addr,
+        server_name: args.server_name.clone(),
+        trust,
+        identity,
+        latency: Duration::from_millis(args.latency_ms),
+    };
+    let mut client = QuicClient::new(TransportSettings::default())?;
+    let endpoint = client.endpoint.clone();
+
+    let name = args
+        .name
+        .or_else(|| std::env::var("USER").ok())
+        .unwrap_or_else(|| "player".to_string());
+    let codecs = match args.codec {
+        Some(codec) => vec![codec],
+        None => Codec::SUPPORTED.to_vec(),
+    };
+    let mut request = JoinRequest { name, codecs, admin_token: args.admin_token, resume_token: None };
 
     println!("Connecting to QUIC server...");
-    let connection = Arc::new(client.connect("127.0.0.1:8080".to_string()).await?);
-    let clone_connection = Arc::clone(&connection);
+    let (link, joined) = target.connect(&mut client, &request).await?;
     println!("Successfully connected to server!");
-    
-    // Create game state
-    let game_state = Arc::new(Mutex::new(GameState {
-        player: Player {
-            x: 0,
-            y: 0,
-            hp: 100,
-            score: 0
-        },
-        enemies: vec![],
-        game_over: false,
-        message: "".to_string()
-    }));
+    let config = joined.config;
+    println!(
+        "Joined as player {} ({}x{} arena, {}Hz)",
+        joined.player_id, config.arena.width, config.arena.height, config.tick_rate
+    );
+    if joined.role == Role::Host {
+        println!("You are the host");
+    }
+    request.resume_token = Some(joined.resume_token);
 
-    // Create shared input variable
-    let latest_input = Arc::new(Mutex::new(String::from("None")));
-    let latest_input_clone = Arc::clone(&latest_input);
+    // all three change when we reconnect, the role also when the host leaves
+    let link = Arc::new(Mutex::new(Arc::new(link)));
+    let player_id = Arc::new(AtomicUsize::new(joined.player_id));
+    let role = Arc::new(Mutex::new(joined.role));
 
     // Game running control flag
     let game_running = Arc::new(AtomicBool::new(true));
     let game_running_clone = Arc::clone(&game_running);
+
+    // our own player moves as soon as a key is pressed, snapshots correct it
+    let predictor = Arc::new(Mutex::new(Predictor::new()));
+    // last two snapshots, the render loop draws in between them
+    let interpolator = Arc::new(Mutex::new(Interpolator::default()));
+    // something the server told only us, shown in place of the game message for a while
+    let notice: Arc<Mutex<Option<(Instant, String)>>> = Arc::new(Mutex::new(None));
+    // the chat line being typed, shown instead of everything else while it is open
+    let draft: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    // the terminal belongs to the renderer from here on, library messages go to the notice
+    let mut reports = client.status.capture();
+    // why the game ended, printed once the terminal is back to normal
+    let exit_reason: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
     
-    // Spawn listener task
+    // Spawn listener task, it also reconnects when the connection drops
     {
-        let connection_clone = Arc::clone(&connection);
-        let latest_input_listener = Arc::clone(&latest_input);
+        let link = Arc::clone(&link);
+        let player_id = Arc::clone(&player_id);
+        let role = Arc::clone(&role);
+        let predictor = Arc::clone(&predictor);
+        let interpolator = Arc::clone(&interpolator);
+        let notice = Arc::clone(&notice);
+        let exit_reason = Arc::clone(&exit_reason);
+        let game_running_clone = Arc::clone(&game_running_clone);
+        let give_up_after = config.resume_grace();
         
         tokio::spawn(async move {
             loop {
@@ -98,132 +168,306 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     break;
                 }
                 
-                let backend_game_state = QuicClient::listen_for_server_messages(Arc::clone(&connection_clone)).await;
-                
-                if let Some(prompt) = render_map(&backend_game_state) {
-                    if prompt == "prompt_restart" {
-                        let mut user_input = String::new();
-                        std::io::stdin().read_line(&mut user_input).expect("Failed to read input");
-                        
-                        match user_input.trim().to_lowercase().as_str() {
-                            "r" => {
-                                // Send restart command
-                                let mut input_lock = latest_input_listener.lock().await;
-                                *input_lock = "Restart".to_string();
-                            },
-                            "q" => {
-                                // Signal to exit
-                                game_running_clone.store(false, Ordering::SeqCst);
-                                
-                                // Send exit command to server
-                                let mut input_lock = latest_input_listener.lock().await;
-                                *input_lock = "Exit".to_string();
-                                
-                                break;
-                            },
-                            _ => {
-                                println!("Invalid input. Enter 'r' to restart or 'q' to quit: ");
-                            }
+                let current = Arc::clone(&*link.lock().unwrap());
+                let Some(message) = current.next_message().await else {
+                    if !game_running_clone.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    // the server hung up on purpose, e.g. it shut down
+                    if let Some(ConnectionError::ApplicationClosed(close)) = current.connection.close_reason() {
+                        let reason = format!("Server closed the connection: {}", String::from_utf8_lossy(&close.reason));
+                        *exit_reason.lock().unwrap() = Some(reason);
+                        game_running_clone.store(false, Ordering::SeqCst);
+                        break;
+                    }
+
+                    let reconnected = reconnect(&mut client, &target, &request, give_up_after, &notice, &game_running_clone).await;
+                    let (new_link, joined) = match reconnected {
+                        Ok(reconnected) => reconnected,
+                        Err(reason) => {
+                            *exit_reason.lock().unwrap() = Some(reason);
+                            game_running_clone.store(false, Ordering::SeqCst);
+                            break;
                         }
+                    };
+
+                    let text = if joined.resumed {
+                        "Reconnected".to_string()
+                    } else {
+                        // the server forgot us, the old inputs mean nothing to the new player
+                        *predictor.lock().unwrap() = Predictor::new();
+                        "Reconnected, your old player was gone".to_string()
+                    };
+                    *notice.lock().unwrap() = Some((Instant::now(), text));
+                    *interpolator.lock().unwrap() = Interpolator::default();
+                    player_id.store(joined.player_id, Ordering::SeqCst);
+                    *role.lock().unwrap() = joined.role;
+                    request.resume_token = Some(joined.resume_token);
+                    *link.lock().unwrap() = Arc::new(new_link);
+                    continue;
+                };
+                let player_id = player_id.load(Ordering::SeqCst);
+
+                let backend_game_state = match message {
+                    ServerMessage::State { snapshot: Snapshot::Full(state), .. } => state,
+                    ServerMessage::Event(GameEvent::ShuttingDown) => {
+                        *exit_reason.lock().unwrap() = Some("Server is shutting down".to_string());
+                        game_running_clone.store(false, Ordering::SeqCst);
+                        break;
+                    }
+                    ServerMessage::Rejected { reason } => {
+                        *notice.lock().unwrap() = Some((Instant::now(), reason));
+                        continue;
                     }
+                    ServerMessage::Event(GameEvent::HostChanged { player_id: host }) if host == player_id => {
+                        *role.lock().unwrap() = Role::Host;
+                        *notice.lock().unwrap() = Some((Instant::now(), "You are the host now".to_string()));
+                        continue;
+                    }
+                    ServerMessage::Event(GameEvent::Chat { name, text, .. }) => {
+                        *notice.lock().unwrap() = Some((Instant::now(), format!("{}: {}", name, text)));
+                        continue;
+                    }
+                    _ => continue,
+                };
+
+                if let Some(player) = backend_game_state.players.get(&player_id) {
+                    predictor.lock().unwrap().reconcile(player, backend_game_state.arena);
                 }
+                interpolator.lock().unwrap().push(backend_game_state, Instant::now());
             }
-            
-            println!("Game client shutting down...");
         });
     }
 
+    // Spawn render task, draws at a fixed rate no matter when snapshots arrive
+    let renderer_task = {
+        let predictor = Arc::clone(&predictor);
+        let notice = Arc::clone(&notice);
+        let draft = Arc::clone(&draft);
+        let player_id = Arc::clone(&player_id);
+        let exit_reason = Arc::clone(&exit_reason);
+
+        tokio::spawn(async move {
+            let mut renderer = match TerminalRenderer::new() {
+                Ok(renderer) => renderer,
+                Err(e) => {
+                    eprintln!("Failed to set up the terminal: {}", e);
+                    game_running_clone.store(false, Ordering::SeqCst);
+                    return;
+                }
+            };
+            let mut frames = tokio::time::interval(Duration::from_secs(1) / FRAME_RATE);
+            frames.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+            while game_running_clone.load(Ordering::SeqCst) {
+                frames.tick().await;
+                while let Ok(text) = reports.try_recv() {
+                    *notice.lock().unwrap() = Some((Instant::now(), text));
+                }
+
+                let Some(mut frame_state) = interpolator.lock().unwrap().sample(Instant::now()) else {
+                    continue;
+                };
+                let player_id = player_id.load(Ordering::SeqCst);
+                if let Some(predicted) = predictor.lock().unwrap().predicted() {
+                    frame_state.players.insert(player_id, predicted.clone());
+                }
+                if let Some((shown_at, text)) = &*notice.lock().unwrap() {
+                    if shown_at.elapsed() < NOTICE_TIME {
+                        frame_state.message = text.clone();
+                    }
+                }
+                if let Some(line) = &*draft.lock().unwrap() {
+                    frame_state.message = format!("Say: {}_", line);
+                }
+
+                let role = *role.lock().unwrap();
+                if let Err(e) = renderer.draw(player_id, role, &frame_state) {
+                    *exit_reason.lock().unwrap() = Some(format!("Failed to draw frame: {}", e));
+                    game_running_clone.store(false, Ordering::SeqCst);
+                }
+            }
+            // dropping the renderer gives the terminal back
+        })
+    };
+
     // Main input loop
     while game_running.load(Ordering::SeqCst) {
-        let input_cmd = fetch_input().await;
+        let typing = draft.lock().unwrap().is_some();
+        let key = fetch_input(typing).await;
 
-        if let Some(cmd) = input_cmd {
-            // Update latest input
-            {
-                let mut input = latest_input_clone.lock().await;
-                *input = cmd.clone();
+        // nothing gets through while we are reconnecting
+        let current = Arc::clone(&*link.lock().unwrap());
+        let connected = current.connection.close_reason().is_none();
+
+        let message = match key {
+            // an input the server never sees would stay pending and be replayed forever
+            KeyPress::Move(command) if connected => {
+                let seq = predictor.lock().unwrap().input(command);
+                ClientMessage::Input { seq, command }
+            }
+            KeyPress::Move(_) => continue,
+            KeyPress::Restart => ClientMessage::Restart,
+            // the server refuses it unless we are the host
+            KeyPress::Shutdown => ClientMessage::Quit,
+            // nothing pressed, nothing to tell the server
+            KeyPress::Idle => continue,
+            KeyPress::Exit => {
+                game_running.store(false, Ordering::SeqCst);
+                break;
+            }
+            KeyPress::Chat => {
+                *draft.lock().unwrap() = Some(String::new());
+                continue;
+            }
+            KeyPress::Type(c) => {
+                if let Some(line) = draft.lock().unwrap().as_mut().filter(|line| line.chars().count() < MAX_CHAT_LEN) {
+                    line.push(c);
+                }
+                continue;
+            }
+            KeyPress::Erase => {
+                if let Some(line) = draft.lock().unwrap().as_mut() {
+                    line.pop();
+                }
+                continue;
             }
-            
-            // Send to server
-            match client.send_message(&Arc::clone(&clone_connection), &cmd).await {
-                Ok(_) => {
-                    // Input sent successfully
-                },
-                Err(e) => eprintln!("Error sending message: {}", e),
+            KeyPress::Cancel => {
+                *draft.lock().unwrap() = None;
+                continue;
             }
-        } else {
-            // Exit key pressed (ESC)
-            game_running.store(false, Ordering::SeqCst);
-            break;
+            KeyPress::Send => match draft.lock().unwrap().take() {
+                Some(text) if !text.trim().is_empty() => ClientMessage::Chat { text },
+                _ => continue,
+            },
+        };
+
+        // Send to server, unless we are reconnecting
+        if !connected {
+            continue;
+        }
+        if let Err(e) = current.send_message(&message).await {
+            *notice.lock().unwrap() = Some((Instant::now(), format!("Error sending message: {}", e)));
         }
     }
 
+    let _ = renderer_task.await;
+    let link = Arc::clone(&*link.lock().unwrap());
+    // the terminal is back to normal now, tell the user why the game ended
+    match link.connection.close_reason() {
+        Some(quinn::ConnectionError::ApplicationClosed(close)) if close.error_code == quic_server::SHUTDOWN_CODE => {
+            println!("Server shut down: {}", String::from_utf8_lossy(&close.reason));
+        }
+        _ => {
+            if let Some(reason) = exit_reason.lock().unwrap().take() {
+                println!("{}", reason);
+            }
+        }
+    }
     println!("Client shutting down...");
+    // tell the server right away instead of letting it wait for the idle timeout
+    link.connection.close(VarInt::from_u32(0), b"client quit");
+    endpoint.wait_idle().await;
     Ok(())
 }
-async fn fetch_input() -> Option<String> {
-    if let Err(_) = enable_raw_mode() {
-        return None;
-    }
 
-    let result = if event::poll(std::time::Duration::from_millis(100)).unwrap_or(false) {
-        if let Ok(Event::Key(key_event)) = event::read() {
-            match key_event.code {
-                KeyCode::Char('a') => Some("MoveLeft".to_string()),
-                KeyCode::Char('d') => Some("MoveRight".to_string()),
-                KeyCode::Char('r') => Some("Restart".to_string()),
-                KeyCode::Char('q') => {
-                    Some("Exit".to_string());
-                    std::process::exit(0);
-                },
-                KeyCode::Left => Some("MoveLeft".to_string()),
-                KeyCode::Right => Some("MoveRight".to_string()),
-                KeyCode::Esc => None,
-                _ => Some("None".to_string()),
-            }
-        } else {
-            Some("None".to_string())
-        }
-    } else {
-        Some("None".to_string())
-    };
-
-    let _ = disable_raw_mode();
-    
-    result
+// everything needed to connect again after the connection drops
+struct Target {
+    addr: SocketAddr,
+    server_name: String,
+    trust: ServerTrust,
+    identity: Option<ClientIdentity>,
+    latency: Duration,
 }
 
-
-fn render_map(state: &GameState) -> Option<String> {
-    let map_width = 13;
-    let map_height = 5;
-    let mut map = vec![vec![' '; map_width]; map_height];
-
-    std::process::Command::new("clear").status().unwrap();
-    
-    if state.game_over {
-        println!("{}", state.message);        
-        return Some("prompt_restart".to_string());
+impl Target {
+    async fn connect(&self, client: &mut QuicClient, request: &JoinRequest) -> Result<(ServerLink, Joined), String> {
+        let connection = client
+            .connect(self.addr, &self.server_name, &self.trust, self.identity.as_ref())
+            .await
+            .map_err(|e| e.to_string())?;
+        let link = client.open_link(connection, self.latency).await.map_err(|e| e.to_string())?;
+        let joined = link.join(request).await.map_err(|e| e.to_string())?;
+        Ok((link, joined))
     }
+}
 
-    if state.player.y < map_height && state.player.x < map_width {
-        map[state.player.y][state.player.x] = 'P';
-    }
+// keeps trying with a growing delay, gives up once the server won't have
+// our player anymore or the user quits, the resume token goes along in request
+// Err is why we stopped trying
+async fn reconnect(
+    client: &mut QuicClient,
+    target: &Target,
+    request: &JoinRequest,
+    give_up_after: Duration,
+    notice: &Mutex<Option<(Instant, String)>>,
+    game_running: &AtomicBool,
+) -> Result<(ServerLink, Joined), String> {
+    let lost_at = Instant::now();
+    let mut backoff = Backoff::new(RECONNECT_FIRST_DELAY, RECONNECT_MAX_DELAY);
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let delay = backoff.next_delay();
+        let text = format!("Connection lost, reconnecting (attempt {})...", attempt);
+        *notice.lock().unwrap() = Some((Instant::now(), text));
+        tokio::time::sleep(delay).await;
+        if !game_running.load(Ordering::SeqCst) {
+            return Err("Lost connection to server".to_string());
+        }
 
-    for enemy in &state.enemies {
-        if enemy.y < map_height && enemy.x < map_width {
-            map[enemy.y][enemy.x] = 'E';
+        match target.connect(client, request).await {
+            Ok(reconnected) => return Ok(reconnected),
+            Err(e) if lost_at.elapsed() >= give_up_after => {
+                return Err(format!("Lost connection to server, gave up reconnecting after {} attempts: {}", attempt, e));
+            }
+            Err(_) => {}
         }
     }
-    
-    for row in map.iter().rev() {
-        let row_string: String = row.iter().collect();
-        print!(".{}.\n\r", row_string);
-    }
-
-    println!("\nPlayer Stats: {:?}", state.player);
-    None
 }
 
+enum KeyPress {
+    Move(InputCommand),
+    Restart,
+    // X asks the server to shut down, host only
+    Shutdown,
+    Idle,
+    // q/ESC, or no terminal to read from
+    Exit,
+    // t opens a chat line
+    Chat,
+    // while a chat line is open
+    Type(char),
+    Erase,
+    Send,
+    Cancel,
+}
 
+// raw mode is on for as long as the renderer is alive
+// typing: a chat line is open, keys go into it instead of moving
+async fn fetch_input(typing: bool) -> KeyPress {
+    if !event::poll(std::time::Duration::from_millis(100)).unwrap_or(false) {
+        return KeyPress::Idle;
+    }
 
+    match event::read() {
+        Ok(Event::Key(key_event)) if typing => match key_event.code {
+            KeyCode::Char(c) => KeyPress::Type(c),
+            KeyCode::Backspace => KeyPress::Erase,
+            KeyCode::Enter => KeyPress::Send,
+            KeyCode::Esc => KeyPress::Cancel,
+            _ => KeyPress::Idle,
+        },
+        Ok(Event::Key(key_event)) => match key_event.code {
+            KeyCode::Char('a') | KeyCode::Left => KeyPress::Move(InputCommand::MoveLeft),
+            KeyCode::Char('d') | KeyCode::Right => KeyPress::Move(InputCommand::MoveRight),
+            KeyCode::Char('r') => KeyPress::Restart,
+            KeyCode::Char('X') => KeyPress::Shutdown,
+            KeyCode::Char('t') => KeyPress::Chat,
+            KeyCode::Char('q') | KeyCode::Esc => KeyPress::Exit,
+            _ => KeyPress::Idle,
+        },
+        Ok(_) => KeyPress::Idle,
+        Err(_) => KeyPress::Exit,
+    }
+}
@@ -1,73 +1,71 @@
-#![cfg(any(feature = "rustls-aws-lc-rs", feature = "rustls-ring"))]
-//! Commonly used code in most examples.
+use std::{error::Error, net::SocketAddr, sync::Arc};
 
-use quinn::{ClientConfig, Endpoint, ServerConfig};
-use rustls::pki_types::{CertificateDer, PrivatePkcs8KeyDer};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use quinn::{ClientConfig, ConnectionError, Endpoint, IdleTimeout, ServerConfig, TransportConfig, TransportErrorCode, VarInt};
 
-use std::{error::Error, net::SocketAddr, sync::Arc};
+use crate::config::TransportSettings;
+
+// the only application protocol both sides speak, anything else is refused in the handshake
+// bump the suffix together with a wire change that old builds can't even handshake with
+pub const ALPN_GAME: &[u8] = b"quic-runner-game/1";
+
+// TLS alert 120 (no_application_protocol) as a QUIC error code
+const NO_APPLICATION_PROTOCOL: u8 = 120;
+
+/*
+Endpoint construction, shared by the server and the client
+- TLS configs come in from quic_server / quic_client::trust, ALPN is set here
+- transport limits and timers come from TransportSettings
+*/
+
+// listening endpoint for the server
+pub fn server_endpoint(
+    bind: SocketAddr,
+    mut tls: rustls::ServerConfig,
+    settings: &TransportSettings,
+) -> Result<Endpoint, Box<dyn Error>> {
+    tls.alpn_protocols = vec![ALPN_GAME.to_vec()];
+
+    let mut server_config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls)?));
+    server_config.transport_config(Arc::new(transport_config(settings)?));
 
-/// Constructs a QUIC endpoint configured for use a client only.
-///
-/// ## Args
-///
-/// - server_certs: list of trusted certificates.
-#[allow(unused)]
-pub fn make_client_endpoint(
-    bind_addr: SocketAddr,
-    server_certs: &[&[u8]],
-) -> Result<Endpoint, Box<dyn Error + Send + Sync + 'static>> {
-    let client_cfg = configure_client(server_certs)?;
-    let mut endpoint = Endpoint::client(bind_addr)?;
-    endpoint.set_default_client_config(client_cfg);
+    let endpoint = Endpoint::server(server_config, bind).map_err(|e| format!("Failed to bind {}: {}", bind, e))?;
     Ok(endpoint)
 }
 
-/// Constructs a QUIC endpoint configured to listen for incoming connections on a certain address
-/// and port.
-///
-/// ## Returns
-///
-/// - a stream of incoming QUIC connections
-/// - server certificate serialized into DER format
-#[allow(unused)]
-pub fn make_server_endpoint(
-    bind_addr: SocketAddr,
-) -> Result<(Endpoint, CertificateDer<'static>), Box<dyn Error + Send + Sync + 'static>> {
-    let (server_config, server_cert) = configure_server()?;
-    let endpoint = Endpoint::server(server_config, bind_addr)?;
-    Ok((endpoint, server_cert))
+// unbound-port endpoint for the client, connect with a config from client_config
+pub fn client_endpoint() -> Result<Endpoint, Box<dyn Error>> {
+    let bind: SocketAddr = "0.0.0.0:0".parse()?;
+    let endpoint = Endpoint::client(bind).map_err(|e| format!("Failed to open client socket: {}", e))?;
+    Ok(endpoint)
 }
 
-/// Builds default quinn client config and trusts given certificates.
-///
-/// ## Args
-///
-/// - server_certs: a list of trusted certificates in DER format.
-fn configure_client(
-    server_certs: &[&[u8]],
-) -> Result<ClientConfig, Box<dyn Error + Send + Sync + 'static>> {
-    let mut certs = rustls::RootCertStore::empty();
-    for cert in server_certs {
-        certs.add(CertificateDer::from(*cert))?;
-    }
+pub fn client_config(mut tls: rustls::ClientConfig, settings: &TransportSettings) -> Result<ClientConfig, Box<dyn Error>> {
+    tls.alpn_protocols = vec![ALPN_GAME.to_vec()];
 
-    Ok(ClientConfig::with_root_certificates(Arc::new(certs))?)
+    let mut client_config = ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls)?));
+    client_config.transport_config(Arc::new(transport_config(settings)?));
+    Ok(client_config)
 }
 
-/// Returns default server configuration along with its certificate.
-fn configure_server()
--> Result<(ServerConfig, CertificateDer<'static>), Box<dyn Error + Send + Sync + 'static>> {
-    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
-    let cert_der = CertificateDer::from(cert.cert);
-    let priv_key = PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
-
-    let mut server_config =
-        ServerConfig::with_single_cert(vec![cert_der.clone()], priv_key.into())?;
-    let transport_config = Arc::get_mut(&mut server_config.transport).unwrap();
-    transport_config.max_concurrent_uni_streams(0_u8.into());
-
-    Ok((server_config, cert_der))
+fn transport_config(settings: &TransportSettings) -> Result<TransportConfig, Box<dyn Error>> {
+    let mut transport = TransportConfig::default();
+    transport
+        .max_idle_timeout(Some(IdleTimeout::try_from(settings.idle_timeout())?))
+        .keep_alive_interval(Some(settings.keep_alive_interval()))
+        .datagram_receive_buffer_size(Some(settings.datagram_receive_buffer))
+        .datagram_send_buffer_size(settings.datagram_send_buffer)
+        .max_concurrent_bidi_streams(VarInt::from_u32(settings.max_bidi_streams))
+        .max_concurrent_uni_streams(VarInt::from_u32(settings.max_uni_streams));
+    Ok(transport)
 }
 
-#[allow(unused)]
-pub const ALPN_QUIC_HTTP: &[&[u8]] = &[b"hq-29"];
\ No newline at end of file
+// true when the handshake failed because the peer doesn't speak ALPN_GAME
+pub fn is_alpn_mismatch(error: &ConnectionError) -> bool {
+    let code = TransportErrorCode::crypto(NO_APPLICATION_PROTOCOL);
+    match error {
+        ConnectionError::ConnectionClosed(close) => close.error_code == code,
+        ConnectionError::TransportError(e) => e.code == code,
+        _ => false,
+    }
+}
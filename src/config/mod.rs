@@ -0,0 +1,245 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::game::Arena;
+
+// every client draws the whole arena in its terminal, and the state has to fit in a frame
+pub const MAX_ARENA_WIDTH: usize = 200;
+pub const MAX_ARENA_HEIGHT: usize = 100;
+pub const MAX_ENEMIES: usize = 256;
+
+/*
+GameConfig
+- the rules the server runs with, everything the simulation used to hard-code
+- read from a .toml or .json file at start, missing keys keep their defaults
+- sent to every client in the Welcome, so they know the arena before the first snapshot
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    // simulation steps per second
+    pub tick_rate: u32,
+    // the score goes up once per interval for every player still alive
+    pub score_interval_ms: u64,
+    // enemies move one row down per interval
+    pub enemy_step_interval_ms: u64,
+    pub max_enemies: usize,
+    pub arena: Arena,
+    pub starting_hp: u32,
+    // a dropped player can reconnect and take their player back for this long, 0 turns it off
+    pub resume_grace_ms: u64,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            tick_rate: 60,
+            score_interval_ms: 1000,
+            enemy_step_interval_ms: 500,
+            max_enemies: 3,
+            arena: Arena::default(),
+            starting_hp: 100,
+            resume_grace_ms: 30_000,
+        }
+    }
+}
+
+impl GameConfig {
+    // the top level keys of the config file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: GameConfig = read_file(path)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    // rejects values the simulation can't run with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tick_rate == 0 || self.tick_rate > 1000 {
+            return Err(format!("tick_rate must be 1..=1000, got {}", self.tick_rate));
+        }
+        if self.score_interval_ms == 0 || self.enemy_step_interval_ms == 0 {
+            return Err("score_interval_ms and enemy_step_interval_ms must be above 0".to_string());
+        }
+        // walls on both sides, a player row and a row for enemies to spawn on
+        if self.arena.width < 3 || self.arena.height < 3 {
+            return Err(format!(
+                "arena must be at least 3x3, got {}x{}",
+                self.arena.width, self.arena.height
+            ));
+        }
+        if self.arena.width > MAX_ARENA_WIDTH || self.arena.height > MAX_ARENA_HEIGHT {
+            return Err(format!(
+                "arena can be at most {}x{}, got {}x{}",
+                MAX_ARENA_WIDTH, MAX_ARENA_HEIGHT, self.arena.width, self.arena.height
+            ));
+        }
+        if self.max_enemies > MAX_ENEMIES {
+            return Err(format!("max_enemies can be at most {}, got {}", MAX_ENEMIES, self.max_enemies));
+        }
+        if self.starting_hp == 0 {
+            return Err("starting_hp must be above 0".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate
+    }
+
+    pub fn score_interval(&self) -> Duration {
+        Duration::from_millis(self.score_interval_ms)
+    }
+
+    pub fn enemy_step_interval(&self) -> Duration {
+        Duration::from_millis(self.enemy_step_interval_ms)
+    }
+
+    pub fn resume_grace(&self) -> Duration {
+        Duration::from_millis(self.resume_grace_ms)
+    }
+}
+
+// the game rules as command line flags, any flag given beats the config file
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct GameOverrides {
+    /// simulation steps per second
+    #[arg(long)]
+    pub tick_rate: Option<u32>,
+    /// how often every live player scores a point
+    #[arg(long)]
+    pub score_interval_ms: Option<u64>,
+    /// how often enemies move down a row
+    #[arg(long)]
+    pub enemy_step_interval_ms: Option<u64>,
+    #[arg(long)]
+    pub max_enemies: Option<usize>,
+    #[arg(long)]
+    pub arena_width: Option<usize>,
+    #[arg(long)]
+    pub arena_height: Option<usize>,
+    #[arg(long)]
+    pub starting_hp: Option<u32>,
+    #[arg(long)]
+    pub resume_grace_ms: Option<u64>,
+}
+
+impl GameOverrides {
+    // config from the file (or the defaults) with the flags on top, checked again after
+    pub fn apply(&self, mut config: GameConfig) -> Result<GameConfig, String> {
+        if let Some(tick_rate) = self.tick_rate {
+            config.tick_rate = tick_rate;
+        }
+        if let Some(ms) = self.score_interval_ms {
+            config.score_interval_ms = ms;
+        }
+        if let Some(ms) = self.enemy_step_interval_ms {
+            config.enemy_step_interval_ms = ms;
+        }
+        if let Some(max_enemies) = self.max_enemies {
+            config.max_enemies = max_enemies;
+        }
+        if let Some(width) = self.arena_width {
+            config.arena.width = width;
+        }
+        if let Some(height) = self.arena_height {
+            config.arena.height = height;
+        }
+        if let Some(hp) = self.starting_hp {
+            config.starting_hp = hp;
+        }
+        if let Some(ms) = self.resume_grace_ms {
+            config.resume_grace_ms = ms;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/*
+TransportSettings
+- how the QUIC connection itself behaves, used by common::server_endpoint/client_config
+- lives under [transport] in the server's config file, the client uses the defaults
+- unlike GameConfig it is not sent to clients
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportSettings {
+    // a connection with no traffic for this long is dropped
+    pub idle_timeout_ms: u64,
+    // pings sent when nothing else is, must be below the idle timeout
+    pub keep_alive_interval_ms: u64,
+    pub datagram_receive_buffer: usize,
+    pub datagram_send_buffer: usize,
+    // the client only ever opens its control stream
+    pub max_bidi_streams: u32,
+    pub max_uni_streams: u32,
+}
+
+impl Default for TransportSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout_ms: 10_000,
+            keep_alive_interval_ms: 3_000,
+            datagram_receive_buffer: 1024 * 1024,
+            datagram_send_buffer: 1024 * 1024,
+            max_bidi_streams: 1,
+            max_uni_streams: 0,
+        }
+    }
+}
+
+impl TransportSettings {
+    // the [transport] table of the config file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        #[derive(Deserialize)]
+        struct File {
+            #[serde(default)]
+            transport: TransportSettings,
+        }
+
+        let File { transport } = read_file(path)?;
+        transport.validate()?;
+        Ok(transport)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.idle_timeout_ms == 0 {
+            return Err("transport.idle_timeout_ms must be above 0".to_string());
+        }
+        if self.keep_alive_interval_ms >= self.idle_timeout_ms {
+            return Err(format!(
+                "transport.keep_alive_interval_ms ({}) must be below idle_timeout_ms ({})",
+                self.keep_alive_interval_ms, self.idle_timeout_ms
+            ));
+        }
+        if self.max_bidi_streams == 0 {
+            return Err("transport.max_bidi_streams must be at least 1 for the control stream".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.idle_timeout_ms)
+    }
+
+    pub fn keep_alive_interval(&self) -> Duration {
+        Duration::from_millis(self.keep_alive_interval_ms)
+    }
+}
+
+// .json files are read as JSON, anything else as TOML
+fn read_file<T: DeserializeOwned>(path: &Path) -> Result<T, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read config {}: {}", path.display(), e))?;
+
+    let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?,
+        _ => toml::from_str(&text)
+            .map_err(|e| format!("Invalid config {}: {}", path.display(), e))?,
+    };
+    Ok(parsed)
+}
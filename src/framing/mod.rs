@@ -0,0 +1,99 @@
+use std::fmt;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/*
+Framing
+- every message on a stream is a u32 big endian length followed by the payload
+- a stream that ends right before a length prefix is a clean end, anywhere else it's a partial frame
+*/
+
+// generous for a GameState, small enough that a bad peer can't make us allocate much
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+const HEADER_LEN: usize = 4;
+
+#[derive(Debug)]
+pub enum FrameError {
+    // length prefix is bigger than what we accept
+    Oversize { len: usize, max: usize },
+    // stream ended in the middle of a frame
+    Partial { expected: usize, received: usize },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Oversize { len, max } => {
+                write!(f, "frame of {} bytes exceeds the {} byte limit", len, max)
+            }
+            FrameError::Partial { expected, received } => {
+                write!(f, "stream ended after {} of {} frame bytes", received, expected)
+            }
+            FrameError::Io(e) => write!(f, "frame io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<std::io::Error> for FrameError {
+    fn from(e: std::io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+
+pub async fn write_frame<W>(send: &mut W, payload: &[u8]) -> Result<(), FrameError>
+where
+    W: AsyncWrite + Unpin,
+{
+    if payload.len() > MAX_FRAME_SIZE {
+        return Err(FrameError::Oversize { len: payload.len(), max: MAX_FRAME_SIZE });
+    }
+
+    send.write_all(&(payload.len() as u32).to_be_bytes()).await?;
+    send.write_all(payload).await?;
+    Ok(())
+}
+
+// Ok(None) means the peer finished the stream between frames
+pub async fn read_frame<R>(recv: &mut R, max: usize) -> Result<Option<Vec<u8>>, FrameError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0u8; HEADER_LEN];
+    match read_full(recv, &mut header).await? {
+        0 => return Ok(None),
+        HEADER_LEN => {}
+        received => return Err(FrameError::Partial { expected: HEADER_LEN, received }),
+    }
+
+    let len = u32::from_be_bytes(header) as usize;
+    if len > max {
+        return Err(FrameError::Oversize { len, max });
+    }
+
+    let mut payload = vec![0u8; len];
+    let received = read_full(recv, &mut payload).await?;
+    if received < len {
+        return Err(FrameError::Partial { expected: len, received });
+    }
+
+    Ok(Some(payload))
+}
+
+// like read_exact, but tells us how far it got before the stream ended
+async fn read_full<R>(recv: &mut R, buf: &mut [u8]) -> std::io::Result<usize>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut filled = 0;
+    while filled < buf.len() {
+        match recv.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
@@ -0,0 +1,175 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Enemy, EnemyId, GameState, Player, PlayerId};
+
+// how many past ticks we can diff against, about a second at 60Hz
+pub const HISTORY_LEN: usize = 64;
+
+// what changed between the base tick and tick
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateDelta {
+    pub base_tick: u64,
+    pub tick: u64,
+    // players that joined or had any field change, sent whole
+    pub players_changed: HashMap<PlayerId, Player>,
+    pub players_removed: Vec<PlayerId>,
+    pub enemies_added: Vec<Enemy>,
+    pub enemies_moved: Vec<(EnemyId, usize, usize)>,
+    pub enemies_removed: Vec<EnemyId>,
+    pub message: Option<String>,
+}
+
+impl StateDelta {
+    pub fn between(base: &GameState, current: &GameState) -> Self {
+        let mut delta = StateDelta {
+            base_tick: base.tick,
+            tick: current.tick,
+            ..Default::default()
+        };
+
+        for (id, player) in &current.players {
+            if base.players.get(id) != Some(player) {
+                delta.players_changed.insert(*id, player.clone());
+            }
+        }
+        delta.players_removed = base
+            .players
+            .keys()
+            .filter(|id| !current.players.contains_key(id))
+            .copied()
+            .collect();
+
+        let base_enemies: HashMap<EnemyId, &Enemy> =
+            base.enemies.iter().map(|enemy| (enemy.id, enemy)).collect();
+        for enemy in &current.enemies {
+            match base_enemies.get(&enemy.id) {
+                None => delta.enemies_added.push(enemy.clone()),
+                Some(old) if old.x != enemy.x || old.y != enemy.y => {
+                    delta.enemies_moved.push((enemy.id, enemy.x, enemy.y))
+                }
+                Some(_) => {}
+            }
+        }
+        delta.enemies_removed = base
+            .enemies
+            .iter()
+            .filter(|old| !current.enemies.iter().any(|enemy| enemy.id == old.id))
+            .map(|old| old.id)
+            .collect();
+
+        if base.message != current.message {
+            delta.message = Some(current.message.clone());
+        }
+
+        delta
+    }
+
+    // rebuilds the state at self.tick, base has to be the state at self.base_tick
+    pub fn apply(&self, base: &GameState) -> GameState {
+        let mut state = base.clone();
+        state.tick = self.tick;
+
+        for id in &self.players_removed {
+            state.players.remove(id);
+        }
+        for (id, player) in &self.players_changed {
+            state.players.insert(*id, player.clone());
+        }
+
+        state.enemies.retain(|enemy| !self.enemies_removed.contains(&enemy.id));
+        for (id, x, y) in &self.enemies_moved {
+            if let Some(enemy) = state.enemies.iter_mut().find(|enemy| enemy.id == *id) {
+                enemy.x = *x;
+                enemy.y = *y;
+            }
+        }
+        state.enemies.extend(self.enemies_added.iter().cloned());
+
+        if let Some(message) = &self.message {
+            state.message = message.clone();
+        }
+
+        state
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Snapshot {
+    Full(GameState),
+    Delta(StateDelta),
+}
+
+/*
+SnapshotHistory (server side, one per client)
+- remembers the last HISTORY_LEN states we sent
+- once the client acks a tick we still have, we send deltas against it
+- no ack yet, or an ack we already forgot about => full snapshot
+*/
+#[derive(Default)]
+pub struct SnapshotHistory {
+    sent: VecDeque<GameState>,
+    acked: Option<u64>,
+}
+
+impl SnapshotHistory {
+    pub fn ack(&mut self, tick: u64) {
+        // acks can arrive out of order, only move forward
+        if self.acked.is_none_or(|acked| tick > acked) {
+            self.acked = Some(tick);
+        }
+    }
+
+    pub fn snapshot_for(&mut self, state: &GameState) -> Snapshot {
+        let base = self
+            .acked
+            .and_then(|acked| self.sent.iter().find(|sent| sent.tick == acked));
+
+        let snapshot = match base {
+            Some(base) => Snapshot::Delta(StateDelta::between(base, state)),
+            None => Snapshot::Full(state.clone()),
+        };
+
+        if self.sent.len() == HISTORY_LEN {
+            self.sent.pop_front();
+        }
+        self.sent.push_back(state.clone());
+
+        snapshot
+    }
+}
+
+// client side, keeps the states deltas may be based on
+#[derive(Default)]
+pub struct SnapshotBuffer {
+    received: VecDeque<GameState>,
+}
+
+impl SnapshotBuffer {
+    // the full state the snapshot describes, None if we don't have its base
+    pub fn receive(&mut self, snapshot: Snapshot) -> Option<&GameState> {
+        let state = match snapshot {
+            Snapshot::Full(state) => state,
+            Snapshot::Delta(delta) => {
+                let base = self.received.iter().find(|state| state.tick == delta.base_tick)?;
+                delta.apply(base)
+            }
+        };
+
+        // anything older than the newest state is useless to render
+        if self.latest().is_some_and(|latest| latest.tick >= state.tick) {
+            return None;
+        }
+
+        if self.received.len() == HISTORY_LEN {
+            self.received.pop_front();
+        }
+        self.received.push_back(state);
+        self.received.back()
+    }
+
+    pub fn latest(&self) -> Option<&GameState> {
+        self.received.back()
+    }
+}
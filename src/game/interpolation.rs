@@ -0,0 +1,57 @@
+use std::time::Instant;
+
+use super::GameState;
+
+/*
+Interpolator (client side)
+- keeps the last two snapshots and when they arrived
+- the renderer samples it at its own pace: we show the previous snapshot right
+  when the current one arrives and slide enemies towards the current one over
+  the time it took that one to arrive, so uneven arrival doesn't make them jump
+- enemies are matched by id, new ones just pop in at their position
+*/
+#[derive(Default)]
+pub struct Interpolator {
+    previous: Option<(Instant, GameState)>,
+    current: Option<(Instant, GameState)>,
+}
+
+impl Interpolator {
+    pub fn push(&mut self, state: GameState, received_at: Instant) {
+        if self.current.as_ref().is_some_and(|(_, current)| current.tick >= state.tick) {
+            return;
+        }
+        self.previous = self.current.take();
+        self.current = Some((received_at, state));
+    }
+
+    pub fn sample(&self, now: Instant) -> Option<GameState> {
+        let (current_at, current) = self.current.as_ref()?;
+        let Some((previous_at, previous)) = &self.previous else {
+            return Some(current.clone());
+        };
+
+        let interval = current_at.saturating_duration_since(*previous_at);
+        let alpha = if interval.is_zero() {
+            1.0
+        } else {
+            let elapsed = now.saturating_duration_since(*current_at);
+            (elapsed.as_secs_f32() / interval.as_secs_f32()).min(1.0)
+        };
+
+        let mut state = current.clone();
+        for enemy in &mut state.enemies {
+            if let Some(old) = previous.enemies.iter().find(|old| old.id == enemy.id) {
+                enemy.x = lerp(old.x, enemy.x, alpha);
+                enemy.y = lerp(old.y, enemy.y, alpha);
+            }
+        }
+        Some(state)
+    }
+}
+
+// the grid only has whole cells, round to the nearest one
+fn lerp(from: usize, to: usize, alpha: f32) -> usize {
+    let value = from as f32 + (to as f32 - from as f32) * alpha;
+    value.round().max(0.0) as usize
+}
@@ -1,44 +1,216 @@
+use std::collections::HashMap;
+
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+pub mod delta;
+pub mod interpolation;
+pub mod prediction;
+pub mod roles;
+pub mod sessions;
+pub mod simulation;
+pub use delta::{Snapshot, SnapshotBuffer, SnapshotHistory, StateDelta};
+pub use interpolation::Interpolator;
+pub use prediction::Predictor;
+pub use roles::{Role, Roles};
+pub use sessions::Sessions;
+pub use simulation::{Clock, ManualClock, Simulation, SystemClock};
+
+// every player is keyed by the id of the connection that spawned it
+pub type PlayerId = usize;
+// enemies get an id when they spawn so snapshots can be diffed
+pub type EnemyId = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum InputCommand {
     MoveLeft,
     MoveRight,
     None,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// one sequenced input from one player, seqs start at 1 and only go up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub player: PlayerId,
+    pub seq: u64,
+    pub command: InputCommand,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Player {
     pub x: usize,
     pub y: usize,
     pub hp: u32,
-    pub score : usize
+    pub score : usize,
+    pub game_over: bool,
+    // newest input seq the server has applied, echoed back in every snapshot
+    pub last_input_seq: u64,
+}
+
+impl Player {
+    // fresh player at the spawn point
+    pub fn spawn() -> Self {
+        Self {
+            x: 5,
+            y: 1,
+            hp: 100,
+            score: 0,
+            game_over: false,
+            last_input_seq: 0,
+        }
+    }
+
+    // left/right movement, clamped to the arena's playable columns
+    // the client predicts with this too, keep it free of anything server only
+    pub fn apply(&mut self, command: InputCommand, arena: &Arena) {
+        if self.game_over {
+            return;
+        }
+
+        match command {
+            InputCommand::MoveLeft => {
+                if self.x > arena.min_x() {
+                    self.x -= 1;
+                }
+            }
+            InputCommand::MoveRight => {
+                if self.x < arena.max_x() {
+                    self.x += 1;
+                }
+            }
+            InputCommand::None => {}
+        }
+    }
+}
+
+
+// size of the world, the server's is the one that counts
+// x is 0..width and y is 0..height, the outer columns are walls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Arena {
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Arena {
+    pub const fn new(width: usize, height: usize) -> Self {
+        Self { width, height }
+    }
+
+    // leftmost column a player or enemy can be in
+    pub fn min_x(&self) -> usize {
+        1
+    }
+
+    // rightmost column a player or enemy can be in
+    pub fn max_x(&self) -> usize {
+        self.width.saturating_sub(2)
+    }
+
+    // enemies enter on the top row
+    pub fn spawn_y(&self) -> usize {
+        self.height.saturating_sub(1)
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height
+    }
 }
 
+impl Default for Arena {
+    fn default() -> Self {
+        Self::new(13, 12)
+    }
+}
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Enemy {
+    pub id: EnemyId,
     pub x: usize,
     pub y: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameState {
-    pub player: Player,
+    // bumped by every simulation step, never goes back (not even on restart)
+    pub tick: u64,
+    // sent along with every full snapshot, clients size their map to it
+    pub arena: Arena,
+    pub players: HashMap<PlayerId, Player>,
     pub enemies : Vec<Enemy>,
-    pub game_over: bool, 
-    pub message : String
+    pub message : String,
+    // only the server hands out enemy ids
+    #[serde(skip)]
+    next_enemy_id: EnemyId,
+}
+
+impl GameState {
+    pub fn new() -> Self {
+        Self::with_arena(Arena::default())
+    }
+
+    pub fn with_arena(arena: Arena) -> Self {
+        let mut state = Self {
+            tick: 0,
+            arena,
+            players: HashMap::new(),
+            enemies: Vec::new(),
+            message: "".to_string(),
+            next_enemy_id: 0,
+        };
+        state.reset_enemies();
+        state
+    }
+
+    // puts every player back at the spawn point and resets the enemies
+    pub fn restart(&mut self) {
+        for player in self.players.values_mut() {
+            // input seqs keep counting across restarts
+            *player = Player { last_input_seq: player.last_input_seq, ..Player::spawn() };
+        }
+        self.reset_enemies();
+        self.message = "".to_string();
+    }
+
+    // new enemies go to the front, like they always did
+    pub fn spawn_enemy(&mut self, x: usize, y: usize) {
+        let id = self.next_enemy_id;
+        self.next_enemy_id += 1;
+        self.enemies.insert(0, Enemy { id, x, y });
+    }
+
+    fn reset_enemies(&mut self) {
+        self.enemies.clear();
+        // the classic start, pulled in for arenas smaller than the default one
+        let (max_x, top) = (self.arena.max_x(), self.arena.spawn_y());
+        self.spawn_enemy(3.min(max_x), 5.min(top));
+        self.spawn_enemy(1, 9.min(top));
+    }
+
+    pub fn any_alive(&self) -> bool {
+        self.players.values().any(|player| !player.game_over)
+    }
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /*
 Map Example
-13x5 gird
+Arena::default() is a 13x12 grid, x is 0..=12 and y is 0..=11
+players live on row 1 and move between columns 1..=11 (min_x..=max_x),
+enemies spawn on row 11 (spawn_y) and walk down to row 1
  ___________
+|  E        |
+|           |
+|     E     |
 |           |
+|     ...   |
 |  E        |
-|     E F   |
-|  F        |
-|     ^     |
+|     P     |
  -----------
 
 forward is like 500ms
@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+
+use super::{Arena, InputCommand, Player};
+
+/*
+Predictor (client side)
+- every input we send gets a seq and is applied to our own copy of the player right away
+- when a snapshot comes in, the server's player is the truth: inputs it already
+  applied (seq <= last_input_seq) are dropped, the rest are replayed on top of it
+- uses Player::apply, so the rules can't drift from the server's
+*/
+pub struct Predictor {
+    next_seq: u64,
+    pending: VecDeque<(u64, InputCommand)>,
+    predicted: Option<Player>,
+    // from the last snapshot, the predicted player is clamped to it
+    arena: Arena,
+}
+
+impl Predictor {
+    pub fn new() -> Self {
+        Self {
+            next_seq: 1,
+            pending: VecDeque::new(),
+            predicted: None,
+            arena: Arena::default(),
+        }
+    }
+
+    // applies the input locally and returns the seq to send it with
+    pub fn input(&mut self, command: InputCommand) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        self.pending.push_back((seq, command));
+        if let Some(player) = &mut self.predicted {
+            player.apply(command, &self.arena);
+        }
+        seq
+    }
+
+    pub fn reconcile(&mut self, authoritative: &Player, arena: Arena) {
+        self.arena = arena;
+        self.pending.retain(|(seq, _)| *seq > authoritative.last_input_seq);
+
+        let mut player = authoritative.clone();
+        for (_, command) in &self.pending {
+            player.apply(*command, &arena);
+        }
+        self.predicted = Some(player);
+    }
+
+    pub fn predicted(&self) -> Option<&Player> {
+        self.predicted.as_ref()
+    }
+}
+
+impl Default for Predictor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use super::PlayerId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    // may restart the game for everyone and shut the server down
+    Host,
+    Player,
+}
+
+/*
+Roles
+- with an admin token configured whoever joins with it is a host,
+  nobody else ever is and a wrong token doesn't get in at all
+- without one the first player to join hosts, when they leave the role
+  goes to whoever has been in the session the longest
+- a player that resumes on a new connection keeps its role and its place, see transfer()
+- the server checks require_host() before obeying a privileged command
+*/
+#[derive(Debug, Default)]
+pub struct Roles {
+    admin_token: Option<String>,
+    // joined players, oldest first
+    members: Vec<(PlayerId, Role)>,
+}
+
+impl Roles {
+    pub fn new(admin_token: Option<String>) -> Self {
+        Self { admin_token, members: Vec::new() }
+    }
+
+    // the role a joining player gets, nothing is recorded until insert()
+    pub fn assign(&self, id: PlayerId, token: Option<&str>) -> Result<Role, String> {
+        // joining twice doesn't change anything
+        if let Some(role) = self.role(id) {
+            return Ok(role);
+        }
+
+        match (&self.admin_token, token) {
+            (Some(expected), Some(token)) if expected == token => Ok(Role::Host),
+            (Some(_), Some(_)) => Err("Wrong admin token".to_string()),
+            (Some(_), None) => Ok(Role::Player),
+            (None, _) if self.hosts().next().is_none() => Ok(Role::Host),
+            (None, _) => Ok(Role::Player),
+        }
+    }
+
+    pub fn insert(&mut self, id: PlayerId, role: Role) {
+        if self.role(id).is_none() {
+            self.members.push((id, role));
+        }
+    }
+
+    // a resumed player's role moves to its new id, None if it had none
+    pub fn transfer(&mut self, from: PlayerId, to: PlayerId) -> Option<Role> {
+        if from == to {
+            return self.role(from);
+        }
+        self.members.retain(|(member, _)| *member != to);
+        let entry = self.members.iter_mut().find(|(member, _)| *member == from)?;
+        entry.0 = to;
+        Some(entry.1)
+    }
+
+    // Some(new host) when the host left and the role moved on
+    pub fn remove(&mut self, id: PlayerId) -> Option<PlayerId> {
+        let index = self.members.iter().position(|(member, _)| *member == id)?;
+        let (_, role) = self.members.remove(index);
+
+        // token hosts are never replaced automatically
+        if role != Role::Host || self.admin_token.is_some() || self.hosts().next().is_some() {
+            return None;
+        }
+        let next = self.members.first_mut()?;
+        next.1 = Role::Host;
+        Some(next.0)
+    }
+
+    pub fn role(&self, id: PlayerId) -> Option<Role> {
+        self.members.iter().find(|(member, _)| *member == id).map(|(_, role)| *role)
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.members.iter().map(|(id, _)| *id)
+    }
+
+    pub fn hosts(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.members.iter().filter(|(_, role)| *role == Role::Host).map(|(id, _)| *id)
+    }
+
+    // action finishes "Only the host can ...", it goes back to the player
+    pub fn require_host(&self, id: PlayerId, action: &str) -> Result<(), String> {
+        match self.role(id) {
+            Some(Role::Host) => Ok(()),
+            _ => Err(format!("Only the host can {}", action)),
+        }
+    }
+}
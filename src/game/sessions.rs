@@ -0,0 +1,93 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use super::{Player, PlayerId};
+
+/*
+Sessions (server side)
+- every joined player gets a resume token in its Welcome
+- when the connection drops the player is parked instead of removed, out of
+  the game (no enemies, no score) but kept for the grace window
+- a Join on a new connection that carries the token claims the old player id,
+  the server moves that player over to the new connection, score and position intact
+- a client can come back before we noticed the old connection is gone,
+  claim() works for live players too, the server closes the old connection then
+- a claimed player can still be parked, the drop may only be noticed after the
+  claim, take() hands it over either way
+*/
+#[derive(Default)]
+pub struct Sessions {
+    grace: Duration,
+    // resume token -> the player it belongs to, one token per player
+    tokens: HashMap<String, PlayerId>,
+    // players whose connection dropped, and since when
+    parked: HashMap<PlayerId, (Player, Instant)>,
+    // players a new connection claimed, not handed over yet
+    claimed: HashSet<PlayerId>,
+}
+
+impl Sessions {
+    pub fn new(grace: Duration) -> Self {
+        Self { grace, tokens: HashMap::new(), parked: HashMap::new(), claimed: HashSet::new() }
+    }
+
+    // a fresh token for a player that just joined, any older one stops working
+    pub fn issue(&mut self, id: PlayerId) -> String {
+        self.forget(id);
+        let mut rng = rand::thread_rng();
+        let token = format!("{:016x}{:016x}", rng.gen::<u64>(), rng.gen::<u64>());
+        self.tokens.insert(token.clone(), id);
+        token
+    }
+
+    // the player id a token was issued to, each token works once
+    pub fn claim(&mut self, token: &str) -> Option<PlayerId> {
+        let id = self.tokens.remove(token)?;
+        self.claimed.insert(id);
+        Some(id)
+    }
+
+    // false when the player can't come back, it should just leave then
+    pub fn park(&mut self, id: PlayerId, player: Player, now: Instant) -> bool {
+        if self.grace.is_zero() || !self.resumable(id) {
+            self.forget(id);
+            return false;
+        }
+        self.parked.insert(id, (player, now));
+        true
+    }
+
+    // hands a claimed player over, the parked one if it was parked already
+    pub fn take(&mut self, id: PlayerId) -> Option<Player> {
+        self.claimed.remove(&id);
+        self.parked.remove(&id).map(|(player, _)| player)
+    }
+
+    // parked players whose grace ran out, they are gone for good
+    pub fn expire(&mut self, now: Instant) -> Vec<PlayerId> {
+        let expired: Vec<PlayerId> = self
+            .parked
+            .iter()
+            .filter(|(_, (_, since))| now.saturating_duration_since(*since) >= self.grace)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired {
+            self.parked.remove(id);
+            self.forget(*id);
+        }
+        expired
+    }
+
+    // the player may still come back, it has a token, was claimed or is parked
+    pub fn resumable(&self, id: PlayerId) -> bool {
+        self.claimed.contains(&id) || self.parked.contains_key(&id) || self.tokens.values().any(|owner| *owner == id)
+    }
+
+    // drops the token of a player that left without being parked
+    pub fn forget(&mut self, id: PlayerId) {
+        self.tokens.retain(|_, owner| *owner != id);
+        self.claimed.remove(&id);
+    }
+}
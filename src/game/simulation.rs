@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::{GameState, Player, PlayerId, PlayerInput};
+use crate::config::GameConfig;
+
+/*
+Simulation
+- owns the rng and the score/enemy timers
+- the rules (intervals, enemy count, starting hp) come from a GameConfig
+- knows nothing about the network or the wall clock
+- same seed + same inputs + same dts => same game
+*/
+pub struct Simulation {
+    rng: StdRng,
+    score_elapsed: Duration,
+    enemy_elapsed: Duration,
+    score_interval: Duration,
+    enemy_step_interval: Duration,
+    max_enemies: usize,
+    starting_hp: u32,
+}
+
+impl Simulation {
+    pub fn new(seed: u64, config: &GameConfig) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            score_elapsed: Duration::ZERO,
+            enemy_elapsed: Duration::ZERO,
+            score_interval: config.score_interval(),
+            enemy_step_interval: config.enemy_step_interval(),
+            max_enemies: config.max_enemies,
+            starting_hp: config.starting_hp,
+        }
+    }
+
+    // puts a fresh player for id at the spawn point
+    pub fn spawn_player(&self, state: &mut GameState, id: PlayerId) {
+        state.players.insert(id, Player { hp: self.starting_hp, ..Player::spawn() });
+    }
+
+    // advances the game by dt, applying each input to its player once
+    // inputs at or below the player's last_input_seq were already applied and are skipped
+    pub fn step(&mut self, state: &mut GameState, inputs: &[PlayerInput], dt: Duration) {
+        if state.any_alive() {
+            self.score_elapsed += dt;
+            self.enemy_elapsed += dt;
+
+            if self.score_elapsed > self.score_interval {
+                for player in state.players.values_mut().filter(|player| !player.game_over) {
+                    player.score += 1;
+                }
+                self.score_elapsed = Duration::ZERO;
+            }
+
+            if self.enemy_elapsed > self.enemy_step_interval {
+                self.step_enemies(state);
+                self.enemy_elapsed = Duration::ZERO;
+            }
+        }
+
+        let arena = state.arena;
+        for input in inputs {
+            let Some(player) = state.players.get_mut(&input.player) else {
+                continue;
+            };
+            if input.seq <= player.last_input_seq {
+                continue;
+            }
+
+            player.last_input_seq = input.seq;
+            player.apply(input.command, &arena);
+        }
+
+        let GameState { players, enemies, .. } = state;
+        for player in players.values_mut().filter(|player| !player.game_over) {
+            if enemies.iter().any(|enemy| enemy.x == player.x && enemy.y == player.y) {
+                player.game_over = true;
+            }
+        }
+
+        state.tick += 1;
+    }
+
+    // resets the state and the timers, as if the game just started
+    pub fn restart(&mut self, state: &mut GameState) {
+        state.restart();
+        for player in state.players.values_mut() {
+            player.hp = self.starting_hp;
+        }
+        self.score_elapsed = Duration::ZERO;
+        self.enemy_elapsed = Duration::ZERO;
+    }
+
+    fn step_enemies(&mut self, state: &mut GameState) {
+        let arena = state.arena;
+        let random_x = self.rng.gen_range(arena.min_x(), arena.max_x() + 1);
+
+        for enemy in state.enemies.iter_mut() {
+            enemy.y -= 1;
+        }
+
+        state.enemies.retain(|enemy| enemy.y > 0);
+
+        while state.enemies.len() < self.max_enemies {
+            state.spawn_enemy(random_x, arena.spawn_y());
+        }
+    }
+}
+
+// where the tick loop gets its dt from
+pub trait Clock {
+    // time passed since the previous call
+    fn tick(&mut self) -> Duration;
+}
+
+// real time, for the live server
+pub struct SystemClock {
+    last: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self { last: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn tick(&mut self) -> Duration {
+        let now = Instant::now();
+        let dt = now - self.last;
+        self.last = now;
+        dt
+    }
+}
+
+// fixed dt per tick, for replays and tests
+pub struct ManualClock {
+    pub step: Duration,
+}
+
+impl Clock for ManualClock {
+    fn tick(&mut self) -> Duration {
+        self.step
+    }
+}
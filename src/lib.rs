@@ -0,0 +1,10 @@
+// shared by the server and client binaries
+pub mod common;
+pub mod config;
+pub mod framing;
+pub mod game;
+pub mod lobby;
+pub mod protocol;
+pub mod quic_client;
+pub mod quic_server;
+pub mod render;
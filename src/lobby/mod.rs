@@ -0,0 +1,186 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+
+use crate::config::GameConfig;
+use crate::game::{PlayerId, PlayerInput, Roles, Sessions};
+use crate::protocol::{self, ClientMessage, GameEvent, ServerMessage, MAX_CHAT_LEN, MAX_NAME_LEN};
+use crate::quic_server::{ConnectionContext, ConnectionId, MessageHandler};
+
+/*
+Lobby (server side)
+- GameHandler answers what can be answered right away (joins, pings) and
+  queues everything else in the Inbox
+- the tick loop drains the Inbox once per tick, it never talks to the handler
+- a connection only counts as joined once its Welcome is queued, see welcomed()
+*/
+
+// inputs a client may have waiting for the next tick
+pub const MAX_QUEUED_INPUTS: usize = 64;
+
+// what the message handler hands over to the tick loop
+#[derive(Default)]
+pub struct Inbox {
+    // connections that finished the handshake, with the name they joined with
+    pub joined: HashMap<ConnectionId, String>,
+    // accepted Joins whose Welcome isn't queued yet, with the player they claimed
+    // the tick loop mustn't see them before that or its messages could beat the Welcome
+    pub pending: HashMap<ConnectionId, (String, Option<PlayerId>)>,
+    // sequenced inputs per joined connection, the tick loop drains them
+    pub inputs: HashMap<ConnectionId, VecDeque<PlayerInput>>,
+    // restart/quit requests since the last tick
+    pub requests: Vec<(ConnectionId, ClientMessage)>,
+    // newest tick each client says it has
+    pub acks: HashMap<ConnectionId, u64>,
+    // who may restart or shut down, filled in on Join, kept while a player may resume
+    pub roles: Roles,
+    // resume tokens and the players waiting for their client to reconnect
+    pub sessions: Sessions,
+    // joined connections that claimed an older player, the tick loop moves it over
+    pub resumed: HashMap<ConnectionId, PlayerId>,
+    // chat lines since the last tick, broadcast with the other events
+    pub chat: Vec<GameEvent>,
+}
+
+impl Inbox {
+    // gone clients free their slot for --max-players and hand on their role once
+    // they can't resume anymore, returns who hosts now because of it
+    pub fn drop_disconnected(&mut self, connected: &[ConnectionId]) -> Vec<PlayerId> {
+        // a client that never saw its Welcome has no token to come back with
+        for id in self.pending.keys().filter(|id| !connected.contains(id)) {
+            self.sessions.forget(*id);
+        }
+        let gone: Vec<PlayerId> = self
+            .roles
+            .members()
+            .filter(|id| !connected.contains(id) && !self.sessions.resumable(*id))
+            .collect();
+        let new_hosts = gone.into_iter().filter_map(|id| self.roles.remove(id)).collect();
+        self.joined.retain(|id, _| connected.contains(id));
+        self.resumed.retain(|id, _| connected.contains(id));
+        self.pending.retain(|id, _| connected.contains(id));
+        new_hosts
+    }
+}
+
+// answers what can be answered right away, queues the rest for the tick loop
+pub struct GameHandler {
+    inbox: Arc<Mutex<Inbox>>,
+    config: GameConfig,
+    max_players: usize,
+}
+
+impl GameHandler {
+    // joins past max_players are rejected
+    pub fn new(inbox: Arc<Mutex<Inbox>>, config: GameConfig, max_players: usize) -> Self {
+        Self { inbox, config, max_players }
+    }
+}
+
+#[async_trait]
+impl MessageHandler for GameHandler {
+    async fn handle(&self, conn: &ConnectionContext, message: ClientMessage) -> Option<ServerMessage> {
+        let id = conn.id;
+        let mut inbox = self.inbox.lock().unwrap();
+
+        match message {
+            ClientMessage::Join { version, name, codecs, admin_token, resume_token: previous_token } => {
+                let players = inbox.joined.len() + inbox.pending.len();
+                if !inbox.joined.contains_key(&id) && players >= self.max_players {
+                    let reason = format!("Server is full ({} players)", self.max_players);
+                    return Some(ServerMessage::Rejected { reason });
+                }
+
+                let role = match inbox.roles.assign(id, admin_token.as_deref()) {
+                    Ok(role) => role,
+                    Err(reason) => return Some(ServerMessage::Rejected { reason }),
+                };
+                let mut reply = protocol::handshake(version, &codecs, id, role, &self.config);
+                if let ServerMessage::Welcome { role, resume_token, resumed, .. } = &mut reply {
+                    let previous = previous_token
+                        .and_then(|token| inbox.sessions.claim(&token))
+                        .filter(|previous| *previous != id);
+                    // a resumed host is still the host
+                    match previous.and_then(|previous| inbox.roles.transfer(previous, id)) {
+                        Some(previous_role) => *role = previous_role,
+                        None => inbox.roles.insert(id, *role),
+                    }
+                    *resumed = previous.is_some();
+                    *resume_token = inbox.sessions.issue(id);
+                    // it ends up in everyone's terminal next to their chat, and in our logs
+                    let name: String = printable(&name).chars().take(MAX_NAME_LEN).collect();
+                    let name = if name.is_empty() { "player".to_string() } else { name };
+                    inbox.pending.insert(id, (name, previous));
+                }
+                Some(reply)
+            }
+            ClientMessage::Ping(nonce) => Some(ServerMessage::Pong(nonce)),
+            ClientMessage::Ack(tick) => {
+                let acked = inbox.acks.entry(id).or_default();
+                *acked = tick.max(*acked);
+                None
+            }
+            message => {
+                let Some(joined_name) = inbox.joined.get(&id).cloned() else {
+                    let reason = "Join before sending game messages".to_string();
+                    return Some(ServerMessage::Rejected { reason });
+                };
+
+                // these change the game for everyone
+                let privileged = match message {
+                    ClientMessage::Restart => Some("restart the game"),
+                    ClientMessage::Quit => Some("shut the server down"),
+                    _ => None,
+                };
+                if let Some(action) = privileged {
+                    if let Err(reason) = inbox.roles.require_host(id, action) {
+                        println!("Player {} tried to {} without being the host", id, action);
+                        return Some(ServerMessage::Rejected { reason });
+                    }
+                }
+
+                match message {
+                    ClientMessage::Input { seq, command } => {
+                        let queue = inbox.inputs.entry(id).or_default();
+                        // a client this far ahead of the tick loop is flooding us
+                        if queue.len() < MAX_QUEUED_INPUTS {
+                            queue.push_back(PlayerInput { player: id, seq, command });
+                        }
+                    }
+                    ClientMessage::Chat { text } => {
+                        let text = printable(&text);
+                        if text.chars().count() > MAX_CHAT_LEN {
+                            let reason = format!("Chat messages can be at most {} characters", MAX_CHAT_LEN);
+                            return Some(ServerMessage::Rejected { reason });
+                        }
+                        if !text.is_empty() {
+                            let name = conn.identity.clone().unwrap_or(joined_name);
+                            inbox.chat.push(GameEvent::Chat { player_id: id, name, text });
+                        }
+                    }
+                    message => inbox.requests.push((id, message)),
+                }
+                None
+            }
+        }
+    }
+
+    // the Welcome is on its way, the tick loop can start sending to this client
+    async fn welcomed(&self, conn: &ConnectionContext) {
+        let mut inbox = self.inbox.lock().unwrap();
+        let Some((name, previous)) = inbox.pending.remove(&conn.id) else {
+            return;
+        };
+        inbox.joined.insert(conn.id, name);
+        if let Some(previous) = previous {
+            inbox.resumed.insert(conn.id, previous);
+        }
+    }
+}
+
+// nobody gets to send escape sequences to everyone's terminal
+fn printable(text: &str) -> String {
+    let text: String = text.chars().filter(|c| !c.is_control()).collect();
+    text.trim().to_string()
+}
@@ -1,56 +1,276 @@
-use chrono::{DateTime, Local, TimeZone, Utc};
-use game::{Enemy, GameState, InputCommand, Player};
-use serde_json;
-use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use quic::config::{GameConfig, GameOverrides, TransportSettings};
+use quic::game::{Clock, GameState, PlayerId, PlayerInput, Roles, Sessions, Simulation, SnapshotHistory, SystemClock};
+use quic::lobby::{GameHandler, Inbox};
+use quic::protocol::{ClientMessage, GameEvent, ServerMessage};
+use quic::quic_server::{cert, ConnectionEvent, ConnectionId, QuicServer, ServerTls};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{broadcast, Mutex};
 use tokio::time::{sleep, Duration};
+use quinn::{ConnectionError, VarInt};
+use tokio_util::sync::CancellationToken;
 
-mod quic_server;
-use quic_server::QuicServer;
-use tokio::sync::Mutex;
-mod game;
-use rand::Rng;
-extern crate rand;
 
+// flags win over the config file, the file wins over the defaults
+#[derive(Parser)]
+#[command(about = "QUIC runner game server")]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// address to listen on
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    bind: SocketAddr,
+    /// server certificate (PEM), make one with gen-cert
+    #[arg(long, default_value = "cert.pem")]
+    cert: PathBuf,
+    /// server private key (PEM), make one with gen-cert
+    #[arg(long, default_value = "key.pem")]
+    key: PathBuf,
+    /// require client certificates signed by this CA (PEM), make one with gen-ca
+    #[arg(long)]
+    client_ca: Option<PathBuf>,
+    /// joins past this many players are rejected
+    #[arg(long, default_value_t = 16)]
+    max_players: usize,
+    /// players joining with this token host the game, without one the first player to join does
+    #[arg(long, env = "QUIC_ADMIN_TOKEN", hide_env_values = true)]
+    admin_token: Option<String>,
+    /// .toml or .json file with the game rules
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    overrides: GameOverrides,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a self-signed certificate and key for the server
+    GenCert(GenCertArgs),
+    /// Create a CA for signing client certificates (use with --client-ca)
+    GenCa(GenCaArgs),
+    /// Issue a client certificate for a player, signed by the CA
+    IssueClient(IssueClientArgs),
+}
+
+// never leave a cert without its key, so all paths are checked before writing any
+fn refuse_existing(paths: &[&PathBuf], force: bool) -> Result<(), String> {
+    if force {
+        return Ok(());
+    }
+    match paths.iter().find(|path| path.exists()) {
+        Some(path) => Err(format!("{} already exists, pass --force to replace it", path.display())),
+        None => Ok(()),
+    }
+}
+
+#[derive(clap::Args)]
+struct GenCertArgs {
+    #[arg(long, default_value = "cert.pem")]
+    cert: PathBuf,
+    #[arg(long, default_value = "key.pem")]
+    key: PathBuf,
+    /// DNS name or IP the certificate is valid for, repeat for more
+    #[arg(long = "san", default_values = ["localhost", "127.0.0.1"])]
+    sans: Vec<String>,
+    /// how many days the certificate is valid for
+    #[arg(long, default_value_t = 365)]
+    days: u32,
+    /// replace existing files
+    #[arg(long)]
+    force: bool,
+}
+
+impl GenCertArgs {
+    fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        refuse_existing(&[&self.cert, &self.key], self.force)?;
+
+        let (cert_pem, key_pem) = cert::generate_self_signed(&self.sans, self.days)?;
+        cert::write_new_file(&self.key, &key_pem, self.force, true)?;
+        cert::write_new_file(&self.cert, &cert_pem, self.force, false)?;
+
+        println!(
+            "Wrote {} and {} for {:?}, valid for {} days",
+            self.cert.display(),
+            self.key.display(),
+            self.sans,
+            self.days
+        );
+        let chain = cert::load_cert_chain(&self.cert)?;
+        println!("Fingerprint (SHA-256): {}", cert::fingerprint(&chain[0]));
+        Ok(())
+    }
+}
+
+#[derive(clap::Args)]
+struct GenCaArgs {
+    #[arg(long, default_value = "ca.pem")]
+    cert: PathBuf,
+    #[arg(long, default_value = "ca-key.pem")]
+    key: PathBuf,
+    /// common name of the CA
+    #[arg(long, default_value = "runner game players")]
+    name: String,
+    #[arg(long, default_value_t = 3650)]
+    days: u32,
+    /// replace existing files
+    #[arg(long)]
+    force: bool,
+}
 
+impl GenCaArgs {
+    fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        refuse_existing(&[&self.cert, &self.key], self.force)?;
 
+        let (cert_pem, key_pem) = cert::generate_ca(&self.name, self.days)?;
+        cert::write_new_file(&self.key, &key_pem, self.force, true)?;
+        cert::write_new_file(&self.cert, &cert_pem, self.force, false)?;
 
+        println!("Wrote CA {} and {}, start the server with --client-ca {}", self.cert.display(), self.key.display(), self.cert.display());
+        Ok(())
+    }
+}
+
+#[derive(clap::Args)]
+struct IssueClientArgs {
+    /// player name, ends up as the certificate's common name
+    name: String,
+    #[arg(long, default_value = "ca.pem")]
+    ca: PathBuf,
+    #[arg(long, default_value = "ca-key.pem")]
+    ca_key: PathBuf,
+    /// defaults to <name>.pem
+    #[arg(long)]
+    cert: Option<PathBuf>,
+    /// defaults to <name>-key.pem
+    #[arg(long)]
+    key: Option<PathBuf>,
+    #[arg(long, default_value_t = 365)]
+    days: u32,
+    /// replace existing files
+    #[arg(long)]
+    force: bool,
+}
+
+impl IssueClientArgs {
+    fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let cert_path = self.cert.clone().unwrap_or_else(|| PathBuf::from(format!("{}.pem", self.name)));
+        let key_path = self.key.clone().unwrap_or_else(|| PathBuf::from(format!("{}-key.pem", self.name)));
+        refuse_existing(&[&cert_path, &key_path], self.force)?;
+
+        let ca_pem = std::fs::read_to_string(&self.ca)
+            .map_err(|e| format!("Failed to read CA {}: {}", self.ca.display(), e))?;
+        let ca_key_pem = std::fs::read_to_string(&self.ca_key)
+            .map_err(|e| format!("Failed to read CA key {}: {}", self.ca_key.display(), e))?;
+
+        let (cert_pem, key_pem) = cert::issue_client_cert(&ca_pem, &ca_key_pem, &self.name, self.days)?;
+        cert::write_new_file(&key_path, &key_pem, self.force, true)?;
+        cert::write_new_file(&cert_path, &cert_pem, self.force, false)?;
+
+        println!(
+            "Wrote {} and {} for {}, connect with --client-cert {} --client-key {}",
+            cert_path.display(),
+            key_path.display(),
+            self.name,
+            cert_path.display(),
+            key_path.display()
+        );
+        Ok(())
+    }
+}
+
+impl Args {
+    fn game_config(&self) -> Result<GameConfig, Box<dyn std::error::Error>> {
+        let config = match &self.config {
+            Some(path) => GameConfig::load(path)?,
+            None => GameConfig::default(),
+        };
+        Ok(self.overrides.apply(config)?)
+    }
+}
+
+// time the goodbye event gets to reach clients before their connections close
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+// Ctrl+C anywhere, SIGTERM too on unix (what docker/systemd send)
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => eprintln!("Failed to listen for SIGTERM: {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        eprintln!("Failed to listen for Ctrl+C: {}", e);
+        // never shut down on our own because of it
+        std::future::pending::<()>().await;
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let latest_input = Arc::new(Mutex::new(String::new()));
-
-    let input_clone = Arc::clone(&latest_input);
-
-    let custom_handler: Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync> =
-        Arc::new(move |data: &[u8]| -> Vec<u8> {
-            let input_clone = Arc::clone(&input_clone);
-            let data_clone = data.to_vec();
-            tokio::spawn(async move {
-                if let Ok(message) = std::str::from_utf8(&data_clone) {
-                    let mut input = input_clone.lock().await;
-                    *input = message.to_string(); // Save to shared state
-                    format!("Server processed: {}", message).into_bytes()
-                } else {
-                    let mut response = Vec::with_capacity(data_clone.len() + 1);
-                    response.push(0x01);
-                    response.extend_from_slice(&data_clone);
-                    response
-                }
-            });
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::GenCert(command)) => return command.run(),
+        Some(Command::GenCa(command)) => return command.run(),
+        Some(Command::IssueClient(command)) => return command.run(),
+        None => {}
+    }
 
-            Vec::new()
-        });
+    let config = args.game_config()?;
+    println!("Game config: {:?}", config);
+    let transport = match &args.config {
+        Some(path) => TransportSettings::load(path)?,
+        None => TransportSettings::default(),
+    };
+
+    let inbox = Arc::new(std::sync::Mutex::new(Inbox {
+        roles: Roles::new(args.admin_token.clone()),
+        sessions: Sessions::new(config.resume_grace()),
+        ..Inbox::default()
+    }));
+
+    let handler = GameHandler::new(Arc::clone(&inbox), config.clone(), args.max_players);
 
     let server = Arc::new(QuicServer::new(
-        "127.0.0.1:8080".to_string(),
-        custom_handler.clone(),
-    ));
+        args.bind,
+        &ServerTls { cert: args.cert.clone(), key: args.key.clone(), client_ca: args.client_ca.clone() },
+        &transport,
+        Arc::new(handler),
+    )?);
+
+    // cancelled by a signal or a client's Quit, everything below winds down on it
+    let shutdown = CancellationToken::new();
+    {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            println!("Shutdown signal received");
+            shutdown.cancel();
+        });
+    }
 
     {
         let server_clone = Arc::clone(&server);
+        let shutdown = shutdown.clone();
         tokio::spawn(async move {
             loop {
-                sleep(Duration::from_secs(5)).await;
+                tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    _ = sleep(Duration::from_secs(5)) => {}
+                }
                 let map = server_clone.connections.lock().unwrap();
                 println!("Client connections: {:?}", map.keys());
             }
@@ -73,125 +293,213 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Game loop logic
 
-    let player = Player {
-        x: 5,
-        y: 1,
-        hp: 100,
-        score: 0,
-    };
-
-    let enemies = vec![Enemy { x: 1, y: 9 }, Enemy { x: 3, y: 5 }];
-    let game_over = false;
-    let message = "".to_string();
+    let state = Arc::new(Mutex::new(GameState::with_arena(config.arena)));
 
-    let state = Arc::new(Mutex::new(GameState { player, enemies, game_over, message }));
-
-    {
+    let game_loop = {
         let state = Arc::clone(&state);
-        let inputs = Arc::clone(&latest_input);
+        let inbox = Arc::clone(&inbox);
         let server_clone = Arc::clone(&server);
-        let game_running = Arc::new(AtomicBool::new(true));
-        let game_running_clone = Arc::clone(&game_running);
+        let shutdown = shutdown.clone();
         
-        let mut score_timer: DateTime<Utc> = Utc::now();
-        let mut enemy_timer: DateTime<Utc> = Utc::now();
-        let tick_duration = Duration::from_millis(1000 / 60); // 60Hz -> 16.67ms per tick
+        // seeded from the wall clock here, fixed seeds give replayable games
+        let mut simulation = Simulation::new(Utc::now().timestamp_millis() as u64, &config);
+        let mut clock = SystemClock::new();
+        // what every client has seen, so we can send it deltas
+        let mut histories: HashMap<ConnectionId, SnapshotHistory> = HashMap::new();
+        let tick_duration = config.tick_duration();
+        let resume_grace = config.resume_grace();
+        // closed connections, their players get despawned on the next tick
+        let mut lifecycle = server.subscribe();
         
         tokio::spawn(async move {
-            while game_running_clone.load(Ordering::SeqCst) {
+            loop {
                 let tick_start = tokio::time::Instant::now(); 
                 let mut state = state.lock().await;
-                let input = inputs.lock().await.clone();
-                
-                if input.as_str() == "Exit" {
-                    state.message = "Game shutting down...".to_string();
-                    game_running_clone.store(false, Ordering::SeqCst);
-                    
-                    let json = serde_json::to_string(&*state).unwrap();
-                    server_clone.broadcast(json.as_bytes()).await;
-                    break;
-                }
-            
-                
-                if !state.game_over {
-                    let current_time: DateTime<Utc> = Utc::now();
-                    
-                    if (current_time - score_timer).num_milliseconds() > 1000 {
-                        state.player.score += 1;
-                        score_timer = current_time;
-                    }
-            
-                    if (current_time - enemy_timer).num_milliseconds() > 500 {
-                        let random_x = rand::thread_rng().gen_range(1, 11);
-            
-                        for enemy in state.enemies.iter_mut() {
-                            enemy.y -= 1;
-                        }
-            
-                        state.enemies.retain(|enemy| enemy.y > 0);
-            
-                        while state.enemies.len() < 3 {
-                            state.enemies.insert(0, Enemy { x: random_x, y: 11 });
-                        }
-                        
-                        enemy_timer = current_time;
+                let mut events = Vec::new();
+
+                let connected: Vec<ConnectionId> =
+                    server_clone.connections.lock().unwrap().keys().copied().collect();
+
+                // everything queued since the last tick is applied exactly once
+                // joined and resumed together, a Join landing in between would lose its resume
+                let (joined, inputs, requests, acks, new_hosts, mut resumed) = {
+                    let mut inbox = inbox.lock().unwrap();
+                    let new_hosts = inbox.drop_disconnected(&connected);
+                    let inputs: Vec<PlayerInput> = inbox.inputs.drain().flat_map(|(_, queue)| queue).collect();
+                    let requests = std::mem::take(&mut inbox.requests);
+                    let acks = std::mem::take(&mut inbox.acks);
+                    let resumed = std::mem::take(&mut inbox.resumed);
+                    (inbox.joined.clone(), inputs, requests, acks, new_hosts, resumed)
+                };
+                // encodes a message for one joined client, in the codec it asked for
+                let encode_for = |id: ConnectionId, message: &ServerMessage| {
+                    if !joined.contains_key(&id) {
+                        return None;
                     }
-            
-                    match input.as_str() {
-                        "MoveLeft" => {
-                            if state.player.x > 1 {
-                                state.player.x -= 1;
+                    server_clone.get_connection(&id).map(|client| client.context.encode(message))
+                };
+
+                // parked players that ran out of time, and chat
+                let (expired, chat) = {
+                    let mut inbox = inbox.lock().unwrap();
+                    (inbox.sessions.expire(Instant::now()), std::mem::take(&mut inbox.chat))
+                };
+                events.extend(chat);
+                for id in expired {
+                    println!("Player {} didn't come back", id);
+                    events.push(GameEvent::PlayerLeft { player_id: id });
+                }
+
+                // one player per live connection that completed the handshake
+                for id in &connected {
+                    if let Some(name) = joined.get(id) {
+                        if !state.players.contains_key(id) {
+                            // a client certificate beats whatever name the client picked
+                            let name = server_clone
+                                .get_connection(id)
+                                .and_then(|client| client.context.identity.clone())
+                                .unwrap_or_else(|| name.clone());
+
+                            // the old player is parked, or still live if we haven't noticed the drop yet
+                            let previous = resumed.remove(id).and_then(|previous| {
+                                let parked = inbox.lock().unwrap().sessions.take(previous);
+                                let player = state.players.remove(&previous).or(parked)?;
+                                Some((previous, player))
+                            });
+
+                            if let Some((previous, player)) = previous {
+                                if let Some(old) = server_clone.get_connection(&previous) {
+                                    old.connection.close(VarInt::from_u32(0), b"resumed on another connection");
+                                }
+                                histories.remove(&previous);
+                                println!("Player {} is back as {} ({})", previous, id, name);
+                                state.players.insert(*id, player);
+                                events.push(GameEvent::PlayerResumed { previous_id: previous, player_id: *id });
+                            } else {
+                                println!("Player {} joined as {}", id, name);
+                                simulation.spawn_player(&mut state, *id);
+                                events.push(GameEvent::PlayerJoined { player_id: *id, name });
                             }
                         }
-                        "MoveRight" => {
-                            if state.player.x < 11 {
-                                state.player.x += 1;
+                    }
+                }
+                loop {
+                    match lifecycle.try_recv() {
+                        Ok(ConnectionEvent::Disconnected { id, reason }) => {
+                            let mut inbox = inbox.lock().unwrap();
+                            match state.players.remove(&id) {
+                                // hung up on purpose, no point waiting for them
+                                Some(_) if matches!(reason, ConnectionError::ApplicationClosed(_)) => {
+                                    inbox.sessions.forget(id);
+                                    println!("Player {} left", id);
+                                    events.push(GameEvent::PlayerLeft { player_id: id });
+                                }
+                                Some(player) => {
+                                    if inbox.sessions.park(id, player, Instant::now()) {
+                                        println!("Player {} dropped, keeping their player for {:?}", id, resume_grace);
+                                    } else {
+                                        println!("Player {} left", id);
+                                        events.push(GameEvent::PlayerLeft { player_id: id });
+                                    }
+                                }
+                                None => inbox.sessions.forget(id),
                             }
+                            histories.remove(&id);
                         }
-                        "None" => {}
-                        "Restart" | "Exit" => {
-                            println!("Unknown input: {}", input);
-                            state.player = Player { x: 5, y: 1, score: 0, hp:100 };
-                            state.enemies =  vec![Enemy { x: 1, y: 9 }, Enemy { x: 3, y: 5 }];
-                            state.game_over = false;
-                            state.message = "".to_string();
-                            score_timer = Utc::now();
-                            enemy_timer = Utc::now();
-                        }
-                        _ => {
-                            println!("Unknown input: {}", input);
+                        Ok(ConnectionEvent::Connected { .. }) => {}
+                        Err(broadcast::error::TryRecvError::Lagged(missed)) => {
+                            // lost track of who left, fall back to who is still here
+                            eprintln!("Missed {} connection events", missed);
+                            // and they can't resume, or their role would never be handed on
+                            let mut inbox = inbox.lock().unwrap();
+                            state.players.retain(|id, _| {
+                                let here = connected.contains(id);
+                                if !here {
+                                    inbox.sessions.forget(*id);
+                                }
+                                here
+                            });
+                            histories.retain(|id, _| connected.contains(id));
                         }
+                        Err(_) => break,
                     }
+                }
+                for id in new_hosts {
+                    println!("Player {} is now the host", id);
+                    events.push(GameEvent::HostChanged { player_id: id });
+                }
+                for (id, tick) in acks {
+                    histories.entry(id).or_default().ack(tick);
+                }
+                
+                if shutdown.is_cancelled() || requests.iter().any(|(_, message)| matches!(message, ClientMessage::Quit)) {
+                    state.message = "Game shutting down...".to_string();
+                    shutdown.cancel();
                     
-                    for enemy in &state.enemies {
+                    // queued now, the connections only close after SHUTDOWN_GRACE
+                    let shutting_down = ServerMessage::Event(GameEvent::ShuttingDown);
+                    server_clone.broadcast_each(|id| encode_for(id, &shutting_down));
+                    break;
+                }
 
-                        println!("crashed {:?} - {:?}", enemy, state.player);
-                        if enemy.x == state.player.x && enemy.y == state.player.y {
-                            state.game_over = true;
-                            state.message = "Game Over! Press 'q' to quit".to_string();
+                if requests.iter().any(|(_, message)| matches!(message, ClientMessage::Restart)) {
+                    simulation.restart(&mut state);
+                    events.push(GameEvent::Restarted);
+                }
 
-                            break;
-                        }
+                let alive: Vec<PlayerId> = state
+                    .players
+                    .iter()
+                    .filter(|(_, player)| !player.game_over)
+                    .map(|(id, _)| *id)
+                    .collect();
+
+                simulation.step(&mut state, &inputs, clock.tick());
+
+                for id in alive {
+                    let player = &state.players[&id];
+                    if player.game_over {
+                        events.push(GameEvent::GameOver { player_id: id, score: player.score });
                     }
                 }
-        
-                // println!("{:?}", *state);
-                let json = serde_json::to_string(&*state).unwrap();
+
+                for event in events {
+                    let event = ServerMessage::Event(event);
+                    server_clone.broadcast_each(|id| encode_for(id, &event));
+                }
         
                 let processing_time = tick_start.elapsed();
                 let remaining_time = tick_duration.saturating_sub(processing_time);
         
                 sleep(remaining_time).await;
-                server_clone.broadcast(json.as_bytes()).await;
+                // every joined client gets the shared state tagged with its own player id
+                server_clone.broadcast_state_each(|id| {
+                    if !state.players.contains_key(&id) {
+                        return None;
+                    }
+                    let snapshot = histories.entry(id).or_default().snapshot_for(&state);
+                    encode_for(id, &ServerMessage::State { player_id: id, snapshot })
+                });
             }
             
             println!("Game server loop terminated.");
-        });
-    }
+        })
+    };
 
 
-    let server_clone: Arc<QuicServer> = Arc::clone(&server);
-    server_clone.accept_loop().await;
+    tokio::select! {
+        _ = server.accept_loop() => {}
+        _ = shutdown.cancelled() => {}
+    }
+    shutdown.cancel();
+
+    // the tick loop sends ShuttingDown to everyone before it returns
+    if let Err(e) = game_loop.await {
+        eprintln!("Game loop failed: {}", e);
+    }
+    println!("Closing connections...");
+    server.shutdown(SHUTDOWN_GRACE).await;
+    println!("Server stopped");
 
     Ok(())
 }
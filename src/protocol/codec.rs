@@ -0,0 +1,76 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/*
+Codec
+- Join and its answer are always Json, so any client can at least be told no
+- the Join lists the codecs the client can speak, best first
+- Welcome carries the one the server picked, both sides switch to it right after
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Codec {
+    // compact, what everyone should be using
+    #[default]
+    Binary,
+    // readable on the wire, for debugging
+    Json,
+}
+
+impl Codec {
+    pub const HANDSHAKE: Codec = Codec::Json;
+
+    // everything this build can speak, best first
+    pub const SUPPORTED: &'static [Codec] = &[Codec::Binary, Codec::Json];
+
+    pub fn encode<T: Serialize>(self, message: &T) -> Vec<u8> {
+        // our messages only contain plain data, this can't fail
+        match self {
+            Codec::Binary => bincode::serialize(message).expect("protocol messages always serialize"),
+            Codec::Json => serde_json::to_vec(message).expect("protocol messages always serialize"),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, CodecError> {
+        match self {
+            Codec::Binary => bincode::deserialize(bytes).map_err(CodecError::Binary),
+            Codec::Json => serde_json::from_slice(bytes).map_err(CodecError::Json),
+        }
+    }
+
+    // first codec in the client's list that we speak too
+    pub fn negotiate(offered: &[Codec]) -> Option<Codec> {
+        offered.iter().copied().find(|codec| Codec::SUPPORTED.contains(codec))
+    }
+}
+
+// for command lines, "binary" or "json"
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "binary" => Ok(Codec::Binary),
+            "json" => Ok(Codec::Json),
+            other => Err(format!("unknown codec '{}', expected binary or json", other)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Binary(bincode::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::Binary(e) => write!(f, "binary decode failed: {}", e),
+            CodecError::Json(e) => write!(f, "json decode failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::GameConfig;
+use crate::game::{InputCommand, PlayerId, Role, Snapshot};
+
+pub mod codec;
+pub use codec::{Codec, CodecError};
+
+// bump whenever a message or a type inside one changes shape
+pub const PROTOCOL_VERSION: u32 = 10;
+
+// longest chat line the server passes on, in characters
+pub const MAX_CHAT_LEN: usize = 200;
+// longer player names are cut to this many characters
+pub const MAX_NAME_LEN: usize = 24;
+
+/*
+Wire protocol
+- client opens with Join, server answers Welcome or Rejected (both Json,
+  everything after that uses the codec picked in the Welcome)
+- after that the client sends Input/Restart/Quit/Ping/Chat, Restart and Quit
+  only from the host, anyone else gets a Rejected back
+- server pushes State every tick and Event when something happens
+- the client acks every state it applied, from then on State carries
+  a delta against the newest acked tick instead of the whole GameState
+- a client that lost its connection joins again on a new one with the
+  resume token from its last Welcome to get its player back
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ClientMessage {
+    // admin_token makes the player a host on servers started with --admin-token
+    // resume_token comes from an earlier Welcome, after a dropped connection
+    Join {
+        version: u32,
+        name: String,
+        codecs: Vec<Codec>,
+        admin_token: Option<String>,
+        resume_token: Option<String>,
+    },
+    // seq goes up by one per input, the server applies each seq once
+    Input { seq: u64, command: InputCommand },
+    Restart,
+    Quit,
+    Ping(u64),
+    // goes out to every joined player as a Chat event
+    Chat { text: String },
+    // newest tick the client has a full state for
+    Ack(u64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    // config is the rules the server runs with, the arena in it sizes the client's map
+    // resumed says the resume token in the Join got the old player back
+    Welcome {
+        version: u32,
+        player_id: PlayerId,
+        codec: Codec,
+        role: Role,
+        config: GameConfig,
+        resume_token: String,
+        resumed: bool,
+    },
+    // a refused Join, or a command this player isn't allowed to send
+    Rejected { reason: String },
+    // the shared state plus the id of the player it is being sent to
+    State { player_id: PlayerId, snapshot: Snapshot },
+    Event(GameEvent),
+    Pong(u64),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum GameEvent {
+    PlayerJoined { player_id: PlayerId, name: String },
+    // the player is gone for good, it didn't come back within the resume grace
+    PlayerLeft { player_id: PlayerId },
+    // the player reconnected, same player under the id of the new connection
+    PlayerResumed { previous_id: PlayerId, player_id: PlayerId },
+    GameOver { player_id: PlayerId, score: usize },
+    Chat { player_id: PlayerId, name: String, text: String },
+    Restarted,
+    // the old host left, this player hosts from now on
+    HostChanged { player_id: PlayerId },
+    ShuttingDown,
+}
+
+// what the server answers to a Join
+pub fn handshake(version: u32, codecs: &[Codec], player_id: PlayerId, role: Role, config: &GameConfig) -> ServerMessage {
+    if version != PROTOCOL_VERSION {
+        return ServerMessage::Rejected {
+            reason: format!(
+                "protocol version mismatch: server speaks {}, client sent {}",
+                PROTOCOL_VERSION, version
+            ),
+        };
+    }
+
+    match Codec::negotiate(codecs) {
+        Some(codec) => ServerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            player_id,
+            codec,
+            role,
+            config: config.clone(),
+            // the server fills these in once the join is accepted
+            resume_token: String::new(),
+            resumed: false,
+        },
+        None => ServerMessage::Rejected {
+            reason: format!("no common codec: server speaks {:?}, client offered {:?}", Codec::SUPPORTED, codecs),
+        },
+    }
+}
@@ -0,0 +1,26 @@
+use std::time::Duration;
+
+// delay before each reconnect attempt, doubling from `initial` up to `max`
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max, next: initial.min(max) }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    // after a successful attempt
+    pub fn reset(&mut self) {
+        self.next = self.initial.min(self.max);
+    }
+}
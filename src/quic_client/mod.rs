@@ -0,0 +1,287 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use quinn::{Connection, Endpoint, RecvStream, SendStream};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Instant;
+use crate::common;
+use crate::config::{GameConfig, TransportSettings};
+use crate::framing::{self, MAX_FRAME_SIZE};
+use crate::game::{PlayerId, Role, Snapshot, SnapshotBuffer};
+use crate::protocol::{ClientMessage, Codec, ServerMessage, PROTOCOL_VERSION};
+use crate::quic_server::DATAGRAM_HEADER_LEN;
+
+pub mod backoff;
+pub mod status;
+pub mod trust;
+pub use backoff::Backoff;
+pub use status::Status;
+pub use trust::{ClientIdentity, ServerTrust};
+
+pub struct QuicClient {
+    pub endpoint: Endpoint,
+    // where the connections and links of this client report to
+    pub status: Status,
+    transport: TransportSettings,
+}
+
+impl QuicClient {
+    pub fn new(transport: TransportSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self {
+            endpoint: common::client_endpoint()?,
+            status: Status::default(),
+            transport,
+        })
+    }
+
+    // server_name has to match a name in the server's certificate
+    pub async fn connect(
+        &mut self,
+        server_addr: SocketAddr,
+        server_name: &str,
+        trust: &ServerTrust,
+        identity: Option<&ClientIdentity>,
+    ) -> Result<Connection, Box<dyn std::error::Error>> {
+        let config = common::client_config(trust.tls_config(identity, &self.status)?, &self.transport)?;
+        self.endpoint.set_default_client_config(config);
+
+        let connection = self.endpoint.connect(server_addr, server_name)?.await.map_err(|e| {
+            if common::is_alpn_mismatch(&e) {
+                format!("{} is not a runner game server, or runs an incompatible version (ALPN mismatch)", server_addr)
+            } else {
+                format!("Failed to connect to {} ({}): {}", server_addr, server_name, e)
+            }
+        })?;
+        self.status.report(format!("Connected to server: {}", connection.remote_address()));
+
+        Ok(connection)
+    }
+
+    // opens the control stream and starts listening for state datagrams
+    // latency is added on the way out and again on the way in, for testing prediction
+    pub async fn open_link(&self, connection: Connection, latency: Duration) -> Result<ServerLink, Box<dyn std::error::Error>> {
+        let (send, recv) = connection
+            .open_bi()
+            .await
+            .map_err(|e| format!("Failed to open control stream: {}", e))?;
+        let (inbox, incoming) = mpsc::unbounded_channel();
+        let (outbox, outgoing) = mpsc::unbounded_channel();
+        let snapshots = Arc::new(std::sync::Mutex::new(SnapshotBuffer::default()));
+        let codec = Arc::new(std::sync::Mutex::new(Codec::HANDSHAKE));
+
+        let deliver = Deliver {
+            connection: connection.clone(),
+            codec: Arc::clone(&codec),
+            snapshots,
+            inbox,
+            latency,
+            status: self.status.clone(),
+        };
+        tokio::spawn(read_control_stream(recv, deliver.clone()));
+        tokio::spawn(read_state_datagrams(connection.clone(), deliver));
+        tokio::spawn(write_control_stream(send, outgoing, self.status.clone()));
+
+        Ok(ServerLink {
+            connection,
+            codec,
+            latency,
+            outbox,
+            incoming: Mutex::new(incoming),
+        })
+    }
+}
+
+/*
+ServerLink
+- one long lived control stream for our messages and the server's events
+- state snapshots come in as datagrams, stale ones are dropped
+- deltas are applied and acked here, State in the inbox is always Snapshot::Full
+- both end up in the same inbox, read it with next_message
+- we talk Codec::HANDSHAKE until the Welcome tells us otherwise
+- both directions go through a queue stamped with when the message is due,
+  that's where the artificial latency comes from
+*/
+pub struct ServerLink {
+    pub connection: Connection,
+    codec: Arc<std::sync::Mutex<Codec>>,
+    latency: Duration,
+    outbox: mpsc::UnboundedSender<(Instant, Vec<u8>)>,
+    incoming: Mutex<mpsc::UnboundedReceiver<(Instant, ServerMessage)>>,
+}
+
+// what we send in the Join, kept around to join again after a reconnect
+#[derive(Debug, Clone)]
+pub struct JoinRequest {
+    pub name: String,
+    // best first
+    pub codecs: Vec<Codec>,
+    pub admin_token: Option<String>,
+    // from the last Welcome, None on the first join
+    pub resume_token: Option<String>,
+}
+
+// what the server told us in its Welcome
+#[derive(Debug, Clone)]
+pub struct Joined {
+    pub player_id: PlayerId,
+    pub role: Role,
+    pub config: GameConfig,
+    pub resume_token: String,
+    // our old player was handed back to us
+    pub resumed: bool,
+}
+
+impl ServerLink {
+    // handshake, has to be the first message on a new link
+    pub async fn join(&self, request: &JoinRequest) -> Result<Joined, Box<dyn std::error::Error>> {
+        let join = ClientMessage::Join {
+            version: PROTOCOL_VERSION,
+            name: request.name.clone(),
+            codecs: request.codecs.clone(),
+            admin_token: request.admin_token.clone(),
+            resume_token: request.resume_token.clone(),
+        };
+        self.send_message(&join).await?;
+        match self.next_message().await {
+            Some(ServerMessage::Welcome { player_id, role, config, resume_token, resumed, .. }) => {
+                Ok(Joined { player_id, role, config, resume_token, resumed })
+            }
+            Some(ServerMessage::Rejected { reason }) => Err(format!("Server rejected join: {}", reason).into()),
+            // e.g. the server refused our client certificate
+            None => match tokio::time::timeout(Duration::from_secs(1), self.connection.closed()).await {
+                Ok(reason) => Err(format!("Connection closed during join: {}", reason).into()),
+                Err(_) => Err("Connection closed during join".into()),
+            },
+            other => Err(format!("Unexpected handshake response: {:?}", other).into()),
+        }
+    }
+
+    pub async fn send_message(&self, message: &ClientMessage) -> Result<(), Box<dyn std::error::Error>> {
+        let frame = self.codec().encode(message);
+        self.outbox
+            .send((Instant::now() + self.latency, frame))
+            .map_err(|_| "Control stream to server is closed")?;
+        Ok(())
+    }
+
+    pub fn codec(&self) -> Codec {
+        *self.codec.lock().unwrap()
+    }
+
+    // next message from the server, None once the connection is gone
+    pub async fn next_message(&self) -> Option<ServerMessage> {
+        let (due, message) = self.incoming.lock().await.recv().await?;
+        tokio::time::sleep_until(due).await;
+        Some(message)
+    }
+}
+
+async fn write_control_stream(
+    mut send: SendStream,
+    mut outgoing: mpsc::UnboundedReceiver<(Instant, Vec<u8>)>,
+    status: Status,
+) {
+    while let Some((due, frame)) = outgoing.recv().await {
+        tokio::time::sleep_until(due).await;
+        if let Err(e) = framing::write_frame(&mut send, &frame).await {
+            status.report(format!("Control stream to server failed: {}", e));
+            return;
+        }
+    }
+    let _ = send.finish();
+}
+
+// shared by both readers, turns wire messages into inbox messages
+#[derive(Clone)]
+struct Deliver {
+    connection: Connection,
+    codec: Arc<std::sync::Mutex<Codec>>,
+    snapshots: Arc<std::sync::Mutex<SnapshotBuffer>>,
+    inbox: mpsc::UnboundedSender<(Instant, ServerMessage)>,
+    latency: Duration,
+    status: Status,
+}
+
+impl Deliver {
+    // false once nobody is reading the inbox anymore
+    fn deliver(&self, bytes: &[u8]) -> bool {
+        let codec = *self.codec.lock().unwrap();
+        let message = match codec.decode::<ServerMessage>(bytes) {
+            Ok(message) => message,
+            Err(e) => {
+                self.status.report(format!("Failed to deserialize ServerMessage: {}", e));
+                return true;
+            }
+        };
+
+        let message = match message {
+            ServerMessage::Welcome { codec, .. } => {
+                // switch before anything else gets decoded
+                *self.codec.lock().unwrap() = codec;
+                message
+            }
+            ServerMessage::State { player_id, snapshot } => {
+                let mut snapshots = self.snapshots.lock().unwrap();
+                let Some(state) = snapshots.receive(snapshot) else {
+                    // stale, or a delta against a state we never got
+                    return true;
+                };
+
+                let ack = ClientMessage::Ack(state.tick);
+                let _ = self.connection.send_datagram(Bytes::from(codec.encode(&ack)));
+
+                ServerMessage::State { player_id, snapshot: Snapshot::Full(state.clone()) }
+            }
+            message => message,
+        };
+
+        self.inbox.send((Instant::now() + self.latency, message)).is_ok()
+    }
+}
+
+async fn read_control_stream(mut recv: RecvStream, deliver: Deliver) {
+    loop {
+        match framing::read_frame(&mut recv, MAX_FRAME_SIZE).await {
+            Ok(Some(frame)) => {
+                if !deliver.deliver(&frame) {
+                    return;
+                }
+            }
+            Ok(None) => return,
+            Err(e) => {
+                deliver.status.report(format!("Control stream from server failed: {}", e));
+                return;
+            }
+        }
+    }
+}
+
+async fn read_state_datagrams(connection: Connection, deliver: Deliver) {
+    let mut last_seq = 0;
+
+    loop {
+        let datagram = match connection.read_datagram().await {
+            Ok(datagram) => datagram,
+            Err(e) => {
+                deliver.status.report(format!("Connection to server lost: {}", e));
+                return;
+            }
+        };
+
+        if datagram.len() < DATAGRAM_HEADER_LEN {
+            continue;
+        }
+        let (header, payload) = datagram.split_at(DATAGRAM_HEADER_LEN);
+        let seq = u64::from_be_bytes(header.try_into().unwrap());
+
+        // reordered or duplicated, we already have something newer
+        if seq <= last_seq {
+            continue;
+        }
+        last_seq = seq;
+
+        if !deliver.deliver(payload) {
+            return;
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+
+/*
+Status
+- things the client library wants the user to know that aren't errors to
+  return, "trusting on first use", a control stream that broke, ...
+- printed to stdout by default
+- a UI that owns the terminal (raw mode, alternate screen) captures them
+  and shows them itself, once it drops the receiver they are printed again
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Status(Arc<Mutex<Option<mpsc::UnboundedSender<String>>>>);
+
+impl Status {
+    pub fn report(&self, message: String) {
+        let message = match &*self.0.lock().unwrap() {
+            Some(sink) => match sink.send(message) {
+                Ok(()) => return,
+                Err(unsent) => unsent.0,
+            },
+            None => message,
+        };
+        println!("{}", message);
+    }
+
+    // everything reported from now on goes to the receiver instead of stdout
+    pub fn capture(&self) -> mpsc::UnboundedReceiver<String> {
+        let (sink, messages) = mpsc::unbounded_channel();
+        *self.0.lock().unwrap() = Some(sink);
+        messages
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+use super::Status;
+use crate::quic_server::cert;
+
+// how the client decides whether the server's certificate is the right one
+pub enum ServerTrust {
+    // the certificates in this PEM file are trusted roots,
+    // the server's own self-signed cert.pem works too
+    Ca(PathBuf),
+    // only a certificate with exactly this SHA-256 fingerprint is accepted
+    Fingerprint(String),
+    // trust on first use: the first fingerprint seen for `host` is saved
+    // to the known hosts file, later connections have to match it
+    KnownHosts { path: PathBuf, host: String },
+    // any certificate is accepted, only for local development
+    InsecureDev,
+}
+
+// our own certificate and key, for servers that require mTLS
+pub struct ClientIdentity {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl ServerTrust {
+    // identity is presented to the server when it asks for a client certificate
+    // status hears about fingerprints trusted on first use
+    pub fn tls_config(&self, identity: Option<&ClientIdentity>, status: &Status) -> Result<rustls::ClientConfig, Box<dyn std::error::Error>> {
+        let provider = Arc::new(crypto::ring::default_provider());
+
+        let verifier: Arc<dyn ServerCertVerifier> = match self {
+            ServerTrust::Ca(path) => {
+                let roots = load_roots(path)?;
+                WebPkiServerVerifier::builder_with_provider(Arc::new(roots), Arc::clone(&provider)).build()?
+            }
+            ServerTrust::Fingerprint(fingerprint) => {
+                let pin = Pin::Fixed(cert::parse_fingerprint(fingerprint)?);
+                Arc::new(PinnedCert { pin, provider: Arc::clone(&provider), status: status.clone() })
+            }
+            ServerTrust::KnownHosts { path, host } => {
+                let pin = Pin::KnownHosts { path: path.clone(), host: host.clone() };
+                Arc::new(PinnedCert { pin, provider: Arc::clone(&provider), status: status.clone() })
+            }
+            ServerTrust::InsecureDev => Arc::new(AcceptAnyCert(Arc::clone(&provider))),
+        };
+
+        let builder = rustls::ClientConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier);
+        let tls = match identity {
+            Some(identity) => {
+                let certs = cert::load_cert_chain(&identity.cert)?;
+                let key = cert::load_private_key(&identity.key)?;
+                builder.with_client_auth_cert(certs, key).map_err(|e| {
+                    format!(
+                        "Client certificate {} doesn't work with key {}: {}",
+                        identity.cert.display(),
+                        identity.key.display(),
+                        e
+                    )
+                })?
+            }
+            None => builder.with_no_client_auth(),
+        };
+        Ok(tls)
+    }
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore, Box<dyn std::error::Error>> {
+    let file = File::open(path).map_err(|e| {
+        format!(
+            "Failed to open CA file {}: {} (pass --ca with the server's certificate, or trust it by --fingerprint or --tofu)",
+            path.display(),
+            e
+        )
+    })?;
+    let mut reader = BufReader::new(file);
+
+    let mut roots = RootCertStore::empty();
+    // a PEM file can hold several certificates
+    for cert in rustls_pemfile::certs(&mut reader) {
+        let cert = cert.map_err(|e| format!("Invalid certificate in {}: {}", path.display(), e))?;
+        roots.add(cert)?;
+    }
+
+    if roots.is_empty() {
+        return Err(format!("No certificates found in {}", path.display()).into());
+    }
+    Ok(roots)
+}
+
+// still checks the handshake signatures, just not who signed the certificate
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[derive(Debug)]
+enum Pin {
+    Fixed(String),
+    KnownHosts { path: PathBuf, host: String },
+}
+
+/*
+PinnedCert
+- the certificate itself is the identity, no CA and no name check
+- known hosts file: one "<host> <fingerprint>" per line, like ssh's
+- a changed fingerprint is refused, the user has to remove the line on purpose
+*/
+#[derive(Debug)]
+struct PinnedCert {
+    pin: Pin,
+    provider: Arc<CryptoProvider>,
+    status: Status,
+}
+
+impl PinnedCert {
+    fn check(&self, fingerprint: &str) -> Result<(), String> {
+        match &self.pin {
+            Pin::Fixed(expected) if expected == fingerprint => Ok(()),
+            Pin::Fixed(expected) => Err(format!(
+                "server certificate fingerprint {} doesn't match the pinned {}",
+                fingerprint, expected
+            )),
+            Pin::KnownHosts { path, host } => match known_fingerprint(path, host)? {
+                Some(known) if known == fingerprint => Ok(()),
+                Some(known) => Err(format!(
+                    "server certificate for {} changed! known hosts has {}, server sent {}. \
+                     If the server really got a new certificate, remove its line from {}",
+                    host,
+                    known,
+                    fingerprint,
+                    path.display()
+                )),
+                None => {
+                    remember_fingerprint(path, host, fingerprint)?;
+                    self.status.report(format!("Trusting {} on first use, fingerprint {}", host, fingerprint));
+                    Ok(())
+                }
+            },
+        }
+    }
+}
+
+fn known_fingerprint(path: &Path, host: &str) -> Result<Option<String>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to open known hosts {}: {}", path.display(), e)),
+    };
+
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| format!("Failed to read known hosts {}: {}", path.display(), e))?;
+        let mut fields = line.split_whitespace();
+        if let (Some(known_host), Some(fingerprint)) = (fields.next(), fields.next()) {
+            if known_host == host {
+                return cert::parse_fingerprint(fingerprint).map(Some);
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn remember_fingerprint(path: &Path, host: &str, fingerprint: &str) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open known hosts {}: {}", path.display(), e))?;
+    writeln!(file, "{} {}", host, fingerprint)
+        .map_err(|e| format!("Failed to write known hosts {}: {}", path.display(), e))
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        self.check(&cert::fingerprint(end_entity))
+            .map(|_| ServerCertVerified::assertion())
+            .map_err(rustls::Error::General)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
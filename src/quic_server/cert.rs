@@ -0,0 +1,149 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Utc};
+use rcgen::{
+    date_time_ymd, BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose,
+};
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+use sha2::{Digest, Sha256};
+
+/*
+Server certificates
+- the server only ever reads cert.pem/key.pem, it never makes them up on start
+- `server gen-cert` is the one place a new self-signed pair gets created
+- existing files are left alone unless --force is given
+- the SHA-256 fingerprint is what clients pin or remember (see quic_client/trust.rs)
+- for mTLS, `server gen-ca` makes a CA and `server issue-client` signs player
+  certificates with it, the subject's common name is the player's identity
+*/
+
+// a self-signed certificate for the given names (DNS names or IPs), as (cert, key) PEM
+pub fn generate_self_signed(
+    subject_alt_names: &[String],
+    validity_days: u32,
+) -> Result<(String, String), rcgen::Error> {
+    let mut params = CertificateParams::new(subject_alt_names.to_vec())?;
+    if let Some(name) = subject_alt_names.first() {
+        params.distinguished_name.push(DnType::CommonName, name.as_str());
+    }
+
+    set_validity(&mut params, validity_days);
+
+    let key_pair = KeyPair::generate()?;
+    let cert = params.self_signed(&key_pair)?;
+    Ok((cert.pem(), key_pair.serialize_pem()))
+}
+
+// a self-signed CA that can only sign certificates, as (cert, key) PEM
+pub fn generate_ca(name: &str, validity_days: u32) -> Result<(String, String), rcgen::Error> {
+    let mut params = CertificateParams::new(Vec::new())?;
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    set_validity(&mut params, validity_days);
+
+    let key_pair = KeyPair::generate()?;
+    let cert = params.self_signed(&key_pair)?;
+    Ok((cert.pem(), key_pair.serialize_pem()))
+}
+
+// a client certificate for `name` signed by the CA, as (cert, key) PEM
+pub fn issue_client_cert(
+    ca_cert_pem: &str,
+    ca_key_pem: &str,
+    name: &str,
+    validity_days: u32,
+) -> Result<(String, String), rcgen::Error> {
+    let ca_key = KeyPair::from_pem(ca_key_pem)?;
+    // rebuilt from the PEM, same subject and key so it signs just like the original
+    let ca_cert = CertificateParams::from_ca_cert_pem(ca_cert_pem)?.self_signed(&ca_key)?;
+
+    let mut params = CertificateParams::new(Vec::new())?;
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    set_validity(&mut params, validity_days);
+
+    let key_pair = KeyPair::generate()?;
+    let cert = params.signed_by(&key_pair, &ca_cert, &ca_key)?;
+    Ok((cert.pem(), key_pair.serialize_pem()))
+}
+
+// valid from the start of today until validity_days from now
+fn set_validity(params: &mut CertificateParams, validity_days: u32) {
+    let day = |date: DateTime<Utc>| date_time_ymd(date.year(), date.month() as u8, date.day() as u8);
+    let now = Utc::now();
+    params.not_before = day(now);
+    params.not_after = day(now + chrono::Duration::days(validity_days.into()));
+}
+
+// the subject's common name, what a client certificate identifies its player by
+pub fn subject_common_name(cert: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert).ok()?;
+    let name = cert.subject().iter_common_name().next()?.as_str().ok()?;
+    Some(name.to_string())
+}
+
+// writes contents to a new file, refusing to replace one unless force is set
+// private files (keys) are only readable by their owner
+pub fn write_new_file(path: &Path, contents: &str, force: bool, private: bool) -> Result<(), String> {
+    let mut options = OpenOptions::new();
+    options.write(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+
+    let mut file = options.open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => {
+            format!("{} already exists, pass --force to replace it", path.display())
+        }
+        _ => format!("Failed to create {}: {}", path.display(), e),
+    })?;
+    file.write_all(contents.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn load_cert_chain(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("Failed to read certificate {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+pub fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| format!("Failed to read key {}: {}", path.display(), e))
+}
+
+// SHA-256 of the DER certificate, as colon separated uppercase hex like openssl prints it
+pub fn fingerprint(cert: &[u8]) -> String {
+    let digest = Sha256::digest(cert);
+    digest.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>().join(":")
+}
+
+// accepts a fingerprint with or without colons, in any case
+pub fn parse_fingerprint(text: &str) -> Result<String, String> {
+    let hex: String = text.chars().filter(|ch| *ch != ':').collect();
+    if hex.len() != 64 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a SHA-256 fingerprint (64 hex digits)", text));
+    }
+
+    let hex = hex.to_ascii_uppercase();
+    let pairs: Vec<&str> = (0..hex.len()).step_by(2).map(|i| &hex[i..i + 2]).collect();
+    Ok(pairs.join(":"))
+}
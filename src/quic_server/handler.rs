@@ -0,0 +1,54 @@
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use rustls::lock::Mutex;
+
+use super::ConnectionId;
+use crate::protocol::{ClientMessage, Codec, ServerMessage};
+
+/*
+MessageHandler
+- QuicServer decodes every frame on a client's control stream and calls
+  handle() with it, one at a time per connection and in order
+- Some(reply) goes back on the same stream before the next message is read,
+  None means there is nothing to answer
+- acks may come in as datagrams, they go through handle() too and their replies are dropped,
+  every other message in a datagram is dropped
+- welcomed() runs once a Welcome reply is queued and the codec switched, anything
+  sent to the connection from then on reaches the client after its Welcome
+*/
+#[async_trait]
+pub trait MessageHandler: Send + Sync {
+    async fn handle(&self, conn: &ConnectionContext, message: ClientMessage) -> Option<ServerMessage>;
+
+    async fn welcomed(&self, _conn: &ConnectionContext) {}
+}
+
+// what the server knows about the connection a message came in on
+pub struct ConnectionContext {
+    pub id: ConnectionId,
+    pub remote: SocketAddr,
+    // common name of the client certificate, only with mTLS
+    pub identity: Option<String>,
+    // Codec::HANDSHAKE until a Welcome went out, the negotiated one after
+    codec: Mutex<Codec>,
+}
+
+impl ConnectionContext {
+    pub fn new(id: ConnectionId, remote: SocketAddr, identity: Option<String>) -> Self {
+        Self { id, remote, identity, codec: Mutex::new(Codec::HANDSHAKE) }
+    }
+
+    pub fn codec(&self) -> Codec {
+        *self.codec.lock().unwrap()
+    }
+
+    pub(crate) fn set_codec(&self, codec: Codec) {
+        *self.codec.lock().unwrap() = codec;
+    }
+
+    // in the codec this client speaks
+    pub fn encode(&self, message: &ServerMessage) -> Vec<u8> {
+        self.codec().encode(message)
+    }
+}
@@ -1,22 +1,65 @@
 use std::collections::HashMap;
-use std::io::Write;
 use std::net::SocketAddr;
-use std::{fs::File, sync::Arc};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use quinn::{Connecting, Connection, Endpoint, Incoming, RecvStream, SendStream, ServerConfig};
-use rcgen::{generate_simple_self_signed, CertifiedKey};
+use bytes::Bytes;
+use quinn::{Connection, ConnectionError, Endpoint, Incoming, RecvStream, SendStream, VarInt};
 use rustls::lock::Mutex;
-use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
-use tokio::task;
-pub type MessageHandler = Arc<dyn Fn(&[u8]) -> Vec<u8> + Send + Sync>;
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::RootCertStore;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::task::TaskTracker;
+
+use crate::common;
+use crate::config::TransportSettings;
+use crate::framing::{self, MAX_FRAME_SIZE};
+use crate::protocol::{ClientMessage, Codec, ServerMessage};
+
+pub mod cert;
+pub mod handler;
+pub use handler::{ConnectionContext, MessageHandler};
+
+// quinn's stable_id, unique per connection for the lifetime of the endpoint
+pub type ConnectionId = usize;
+
+// application error code connections are closed with when the server shuts down
+pub const SHUTDOWN_CODE: VarInt = VarInt::from_u32(1);
+// and when a client lets its control stream back up past CONTROL_BACKLOG
+pub const BACKLOG_CODE: VarInt = VarInt::from_u32(2);
+// frames a client may leave unread on its control stream before we give up on it
+pub const CONTROL_BACKLOG: usize = 256;
+// how long shutdown waits for connection tasks after closing the endpoint
+const TASK_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+// lifecycle events a subscriber can miss before it starts lagging
+const EVENT_CAPACITY: usize = 256;
+
+// every state datagram starts with a u64 big endian sequence number
+pub const DATAGRAM_HEADER_LEN: usize = 8;
 
 /*
 QuicServer
-- generate certificate
-- make server config
-- create and bind endpoint
+- load the certificate and key (see cert.rs for making them)
+- create and bind endpoint (common::server_endpoint, game ALPN + transport settings)
 - accept connection
-- handle message from stream
+- connections are in `connections` from the handshake until they close
+  (either side closing, or the idle timeout), subscribe() tells you when
+- shutdown() closes every connection with SHUTDOWN_CODE and waits for
+  the per connection tasks to finish
+- the first bi stream a client opens is its control stream,
+  messages and events go over it in order and reliably
+- every message the client sends goes to the MessageHandler (handler.rs),
+  its reply goes back in the codec the connection negotiated
+- state snapshots go out as sequenced datagrams, they are
+  allowed to get lost since the next tick replaces them
+- a snapshot too big for a datagram only goes on the control stream when
+  nothing else is waiting there, otherwise it is dropped like a lost datagram
+- the control stream queue is bounded, a client that doesn't read it
+  is closed with BACKLOG_CODE instead of making us buffer forever
 */
 pub struct QuicServer {
     // QUIC needs the Endpoint to stay alive while the server is running.
@@ -25,27 +68,103 @@ pub struct QuicServer {
     // - Tie its lifetime to your server
     // - Prevent accidental early drops
     endpoint: Endpoint,
-    message_handler: MessageHandler,
+    message_handler: Arc<dyn MessageHandler>,
 
     // for storing multiple client so i can send message indvidually
-    pub connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    pub connections: Arc<Mutex<HashMap<ConnectionId, ClientHandle>>>,
+
+    // sequence number of the last state datagram we sent
+    datagram_seq: AtomicU64,
+
+    events: broadcast::Sender<ConnectionEvent>,
+
+    // every task we spawn per connection, so shutdown can wait for them
+    tasks: TaskTracker,
 }
 
-impl QuicServer {
-    // creates server
-    pub fn new(endpoint_addr: String, message_handler: MessageHandler) -> Self {
-        let addr: SocketAddr = endpoint_addr.parse().unwrap();
-        let server_config = generate_server_config();
-        let endpoint = Endpoint::server(server_config, addr).expect("Failed to create endpoint");
+// what happened to a connection, in order per connection
+#[derive(Debug, Clone)]
+pub enum ConnectionEvent {
+    // handshake done, the connection is in the map
+    Connected { id: ConnectionId, remote: SocketAddr, identity: Option<String> },
+    // closed, the connection is no longer in the map
+    // reason is ApplicationClosed when the client hung up on purpose
+    Disconnected { id: ConnectionId, reason: ConnectionError },
+}
 
-        Self {
+// where the server's TLS material lives
+pub struct ServerTls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    // with a client CA every client has to present a certificate signed by it (mTLS)
+    pub client_ca: Option<PathBuf>,
+}
+
+// everything we keep per connected client
+#[derive(Clone)]
+pub struct ClientHandle {
+    pub connection: Connection,
+    // id, certificate name and codec, the same one the handler sees
+    pub context: Arc<ConnectionContext>,
+    // frames waiting to be written to the client's control stream, at most CONTROL_BACKLOG
+    control: mpsc::Sender<Vec<u8>>,
+}
+
+impl ClientHandle {
+    // queues a frame on the control stream, false if the client is gone
+    // or so far behind that we just closed it
+    pub fn send_control(&self, data: Vec<u8>) -> bool {
+        match self.control.try_send(data) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.connection.close(BACKLOG_CODE, b"not reading the control stream");
+                false
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        }
+    }
+
+    // nothing waiting to be written, so one more frame can't hold anything up
+    fn control_idle(&self) -> bool {
+        self.control.capacity() == self.control.max_capacity()
+    }
+
+    // same, encoded in the client's codec
+    pub fn send_message(&self, message: &ServerMessage) -> bool {
+        self.send_control(self.context.encode(message))
+    }
+}
+
+impl QuicServer {
+    // creates server, the files in tls have to exist already
+    pub fn new(
+        bind: SocketAddr,
+        tls: &ServerTls,
+        transport: &TransportSettings,
+        message_handler: Arc<dyn MessageHandler>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let endpoint = common::server_endpoint(bind, load_server_tls(tls)?, transport)?;
+
+        Ok(Self {
             endpoint,
             message_handler,
             connections: Arc::new(Mutex::new(HashMap::new())),
-        }
+            datagram_seq: AtomicU64::new(0),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            tasks: TaskTracker::new(),
+        })
     }
 
-    // handle connection
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    // connection events from now on, e.g. to despawn the player of a closed connection
+    pub fn subscribe(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.events.subscribe()
+    }
+
+    // handle connection, returns once the endpoint is closed
     pub async fn accept_loop(&self) {
         println!(
             "Server listening on {}",
@@ -57,10 +176,12 @@ impl QuicServer {
         while let Some(connecting) = incoming {
             let handler = Arc::clone(&self.message_handler);
             let connections = Arc::clone(&self.connections);
+            let events = self.events.clone();
+            let tasks = self.tasks.clone();
 
             // Move everything needed into the task
-            task::spawn(async move {
-                if let Err(e) = handle_connection(connecting, handler, connections).await {
+            self.tasks.spawn(async move {
+                if let Err(e) = handle_connection(connecting, handler, connections, events, tasks).await {
                     eprintln!("Connection failed: {}", e);
                 }
             });
@@ -69,122 +190,236 @@ impl QuicServer {
         }
     }
 
-    // handle message 
-    pub async fn send_to(&self, addr: SocketAddr, data: &[u8]) -> Result<(), String> {
-        if let Some(conn) = self.get_connection(&addr) {
-            match conn.open_bi().await {
-                Ok((mut send, _recv)) => {
-                    send.write_all(data).await.map_err(|e| e.to_string())?;
-                    send.finish().map_err(|e| e.to_string())?;
-                    Ok(())
-                }
-                Err(e) => Err(format!("Failed to open stream: {}", e)),
-            }
-        } else {
-            Err(format!("No connection for address: {}", addr))
+    // reliable, ordered send on the client's control stream
+    pub fn send_to(&self, id: ConnectionId, data: &[u8]) -> Result<(), String> {
+        match self.get_connection(&id) {
+            Some(client) if client.send_control(data.to_vec()) => Ok(()),
+            Some(_) => Err(format!("Control stream for {} is closed", id)),
+            None => Err(format!("No connection for id: {}", id)),
         }
     }
 
-    pub async fn broadcast(&self, data: &[u8]) {
-    let connections = Arc::clone(&self.connections);
-    let data = data.to_vec();
-
-    // Lock outside spawn
-    let locked_connections = connections.lock().unwrap().clone();
+    // reliable broadcast on every control stream, for events
+    pub fn broadcast(&self, data: &[u8]) {
+        self.broadcast_each(|_| Some(data.to_vec()));
+    }
 
-    for (addr, conn) in locked_connections {
-        let conn = conn.clone();
-        let data = data.clone();
+    // same as broadcast, but the payload is built per connection, None skips it
+    pub fn broadcast_each<F>(&self, mut make_payload: F)
+    where
+        F: FnMut(ConnectionId) -> Option<Vec<u8>>,
+    {
+        let locked_connections = self.connections.lock().unwrap().clone();
+
+        for (id, client) in locked_connections {
+            let Some(payload) = make_payload(id) else {
+                continue;
+            };
+            if !client.send_control(payload) {
+                eprintln!("Failed to queue message for {}", id);
+            }
+        }
+    }
 
-        tokio::spawn(async move {
-            if let Ok((mut send, _recv)) = conn.open_bi().await {
-                if let Err(e) = send.write_all(&data).await {
-                    eprintln!("Failed to send to {}: {}", addr, e);
+    // unreliable per-connection broadcast for state snapshots, None skips the connection
+    pub fn broadcast_state_each<F>(&self, mut make_payload: F)
+    where
+        F: FnMut(ConnectionId) -> Option<Vec<u8>>,
+    {
+        let seq = self.datagram_seq.fetch_add(1, Ordering::SeqCst) + 1;
+        let locked_connections = self.connections.lock().unwrap().clone();
+
+        for (id, client) in locked_connections {
+            let Some(payload) = make_payload(id) else {
+                continue;
+            };
+
+            let fits = client
+                .connection
+                .max_datagram_size()
+                .is_some_and(|max| DATAGRAM_HEADER_LEN + payload.len() <= max);
+
+            if !fits {
+                // too big for a datagram (or the peer has them disabled), take the slow
+                // path, but never queue state behind state or in front of events
+                if client.control_idle() {
+                    client.send_control(payload);
                 }
-                let _ = send.finish();
+                continue;
+            }
+
+            let mut datagram = Vec::with_capacity(DATAGRAM_HEADER_LEN + payload.len());
+            datagram.extend_from_slice(&seq.to_be_bytes());
+            datagram.extend_from_slice(&payload);
+
+            if let Err(e) = client.connection.send_datagram(Bytes::from(datagram)) {
+                eprintln!("Failed to send datagram to {}: {}", client.connection.remote_address(), e);
             }
-        });
+        }
     }
-}
 
-    pub fn get_connection(&self, addr: &SocketAddr) -> Option<Connection> {
+    pub fn get_connection(&self, id: &ConnectionId) -> Option<ClientHandle> {
         let connections = self.connections.lock().unwrap();
-        connections.get(addr).cloned()
+        connections.get(id).cloned()
     }
 
-    pub async fn listen_client(&self){
+    // anything already queued on the control streams gets `grace` to go out,
+    // then every connection is closed and we wait for the peers and our tasks
+    pub async fn shutdown(&self, grace: Duration) {
+        tokio::time::sleep(grace).await;
 
-    }
+        self.endpoint.close(SHUTDOWN_CODE, b"server shutting down");
+        self.endpoint.wait_idle().await;
 
+        self.tasks.close();
+        if tokio::time::timeout(TASK_DRAIN_TIMEOUT, self.tasks.wait()).await.is_err() {
+            eprintln!("{} connection tasks still running after shutdown", self.tasks.len());
+        }
+    }
 }
 // helper for handle connections
 pub async fn handle_connection(
     connecting: Incoming,
-    message_handler: MessageHandler,
-    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    message_handler: Arc<dyn MessageHandler>,
+    connections: Arc<Mutex<HashMap<ConnectionId, ClientHandle>>>,
+    events: broadcast::Sender<ConnectionEvent>,
+    tasks: TaskTracker,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let connection = connecting.await?;
+    let remote = connecting.remote_address();
+    let connection = connecting.await.map_err(|e| {
+        if common::is_alpn_mismatch(&e) {
+            format!("{} doesn't speak the game protocol (ALPN mismatch)", remote)
+        } else {
+            format!("{}: {}", remote, e)
+        }
+    })?;
 
     println!(
         "Connection established from: {}",
         connection.remote_address()
     );
 
+    let identity = peer_identity(&connection);
+    if let Some(name) = &identity {
+        println!("Client certificate identifies {} as {}", connection.remote_address(), name);
+    }
+
+    let id = connection.stable_id();
+    let (control, outbox) = mpsc::channel(CONTROL_BACKLOG);
+    let context = Arc::new(ConnectionContext::new(id, remote, identity.clone()));
+
     {
         let mut map = connections.lock().unwrap();
-        map.insert(connection.remote_address(), connection.clone());
+        let client = ClientHandle { connection: connection.clone(), context: Arc::clone(&context), control: control.clone() };
+        map.insert(id, client);
+    }
+    // nobody listening is fine
+    let _ = events.send(ConnectionEvent::Connected { id, remote, identity });
+
+    if let Err(e) = process_connection(connection.clone(), context, message_handler, control, outbox, tasks).await {
+        eprintln!("Connection {} failed: {}", id, e);
     }
 
-    process_connection(connection, message_handler).await?;
+    // covers the client closing, us closing it and the idle timeout
+    let reason = connection.closed().await;
+    println!("Connection ended: {}", reason);
+
+    connections.lock().unwrap().remove(&id);
+    let _ = events.send(ConnectionEvent::Disconnected { id, reason });
 
     Ok(())
 }
 
+// who the client certificate says the peer is, None without mTLS
+fn peer_identity(connection: &Connection) -> Option<String> {
+    let chain = connection.peer_identity()?.downcast::<Vec<CertificateDer<'static>>>().ok()?;
+    cert::subject_common_name(chain.first()?)
+}
+
 async fn process_connection(
     connection: Connection,
-    message_handler: MessageHandler,
+    context: Arc<ConnectionContext>,
+    message_handler: Arc<dyn MessageHandler>,
+    control: mpsc::Sender<Vec<u8>>,
+    outbox: mpsc::Receiver<Vec<u8>>,
+    tasks: TaskTracker,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    loop {
-        match connection.accept_bi().await {
-            Ok((send, recv)) => {
-                if let Err(e) = handle_stream(send, recv, Arc::clone(&message_handler)).await {
-                    eprintln!("Stream error: {}", e);
-                }
-            }
-            Err(e) => {
-                println!("Connection ended: {}", e);
-                break;
-            }
-        }
+    // the client opens its control stream right after connecting
+    let Ok((send, recv)) = connection.accept_bi().await else {
+        // closed before it got that far
+        return Ok(());
+    };
+
+    tasks.spawn(write_control_stream(send, outbox));
+
+    tasks.spawn(read_client_datagrams(connection.clone(), Arc::clone(&context), Arc::clone(&message_handler)));
+
+    if let Err(e) = read_control_stream(recv, context, message_handler, control).await {
+        eprintln!("Stream error: {}", e);
     }
 
     Ok(())
 }
 
-async fn handle_stream(
-    mut send: SendStream,
-    mut recv: RecvStream,
-    message_handler: MessageHandler,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut buffer = vec![0; 1024];
+// drains the outbox onto the control stream until either side goes away
+async fn write_control_stream(mut send: SendStream, mut outbox: mpsc::Receiver<Vec<u8>>) {
+    while let Some(frame) = outbox.recv().await {
+        if let Err(e) = framing::write_frame(&mut send, &frame).await {
+            eprintln!("Control stream write failed: {}", e);
+            return;
+        }
+    }
+    let _ = send.finish();
+}
 
-    match recv.read(&mut buffer).await? {
-        Some(bytes) => {
-            let data = &buffer[..bytes];
+// small unreliable messages from the client (acks), their answers are dropped
+async fn read_client_datagrams(
+    connection: Connection,
+    context: Arc<ConnectionContext>,
+    message_handler: Arc<dyn MessageHandler>,
+) {
+    while let Ok(datagram) = connection.read_datagram().await {
+        // a lost or mangled ack is no different from one that never came,
+        // anything else has to come over the control stream, in order
+        if let Ok(message @ ClientMessage::Ack(_)) = context.codec().decode::<ClientMessage>(&datagram) {
+            message_handler.handle(&context, message).await;
+        }
+    }
+}
 
-            if let Ok(message) = std::str::from_utf8(data) {
-                // println!("Received message: {}", message);
-            } else {
-                println!("Received binary data: {} bytes", bytes);
+async fn read_control_stream(
+    mut recv: RecvStream,
+    context: Arc<ConnectionContext>,
+    message_handler: Arc<dyn MessageHandler>,
+    control: mpsc::Sender<Vec<u8>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    while let Some(data) = framing::read_frame(&mut recv, MAX_FRAME_SIZE).await? {
+        let codec = context.codec();
+        let reply = match codec.decode::<ClientMessage>(&data) {
+            // the answer to a Join is in the handshake codec, whatever it says
+            Ok(message @ ClientMessage::Join { .. }) => message_handler
+                .handle(&context, message)
+                .await
+                .map(|reply| (Codec::HANDSHAKE, reply)),
+            Ok(message) => message_handler.handle(&context, message).await.map(|reply| (codec, reply)),
+            Err(e) => {
+                let reason = format!("Malformed message: {}", e);
+                Some((codec, ServerMessage::Rejected { reason }))
             }
-
-            let response = message_handler(data);
-
-            send.write_all(&response).await?;
-            send.finish();
+        };
+        let Some((codec, reply)) = reply else {
+            continue;
+        };
+
+        let frame = codec.encode(&reply);
+        // a client that doesn't read its replies stops getting its requests read
+        if control.send(frame).await.is_err() {
+            break;
         }
-        None => {
-            println!("Empty stream received");
+        // both sides switch right after the Welcome
+        if let ServerMessage::Welcome { codec, .. } = &reply {
+            context.set_codec(*codec);
+            message_handler.welcomed(&context).await;
         }
     }
 
@@ -192,36 +427,41 @@ async fn handle_stream(
 }
 
 // handling tls
-pub fn generate_sign_cert() -> (String, String) {
-    let subject_alt_names = vec!["localhost".to_string()];
-    let CertifiedKey { cert, key_pair } = generate_simple_self_signed(subject_alt_names).unwrap();
-    (cert.pem(), key_pair.serialize_pem())
-}
-
-pub fn generate_server_config() -> ServerConfig {
-    let (cert_pem, key_pem) = generate_sign_cert();
-
-    let cert_path = "cert.pem";
-    let key_path = "key.pem";
-
-    File::create(cert_path)
-        .unwrap()
-        .write_all(cert_pem.as_bytes())
-        .unwrap();
+pub fn load_server_tls(tls: &ServerTls) -> Result<rustls::ServerConfig, Box<dyn std::error::Error>> {
+    let ServerTls { cert: cert_path, key: key_path, client_ca } = tls;
+    if !cert_path.exists() || !key_path.exists() {
+        return Err(format!(
+            "No certificate at {} / key at {}, create them with `server gen-cert`",
+            cert_path.display(),
+            key_path.display()
+        )
+        .into());
+    }
 
-    File::create(key_path)
-        .unwrap()
-        .write_all(key_pem.as_bytes())
-        .unwrap();
+    let certs = cert::load_cert_chain(cert_path)?;
+    // what clients pin with --fingerprint, or get asked about with --tofu
+    println!("Certificate fingerprint (SHA-256): {}", cert::fingerprint(&certs[0]));
+    let private_key = cert::load_private_key(key_path)?;
 
-    let certs: Vec<CertificateDer> = CertificateDer::pem_file_iter(cert_path)
-        .unwrap()
-        .map(|cert| cert.unwrap())
-        .collect();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = rustls::ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_protocol_versions(&[&rustls::version::TLS13])?;
 
-    let private_key = PrivateKeyDer::from_pem_file(key_path).unwrap();
+    let builder = match client_ca {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for ca in cert::load_cert_chain(ca_path)? {
+                roots.add(ca).map_err(|e| format!("Invalid client CA {}: {}", ca_path.display(), e))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build()?;
+            println!("Requiring client certificates signed by {}", ca_path.display());
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
 
-    let mut server_config: quinn::ServerConfig =
-        quinn::ServerConfig::with_single_cert(certs, private_key).unwrap();
-    server_config
+    let tls_config = builder
+        .with_single_cert(certs, private_key)
+        .map_err(|e| format!("Certificate {} doesn't work with key {}: {}", cert_path.display(), key_path.display(), e))?;
+    Ok(tls_config)
 }
@@ -0,0 +1,28 @@
+use std::io;
+
+use super::{compose, CellBuffer, Renderer};
+use crate::game::{GameState, PlayerId, Role};
+
+// keeps the last frame in memory instead of drawing it, for tests and tools
+#[derive(Default)]
+pub struct MemoryRenderer {
+    frame: Option<CellBuffer>,
+}
+
+impl MemoryRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the last frame as text, empty before the first draw
+    pub fn frame(&self) -> String {
+        self.frame.as_ref().map(CellBuffer::to_text).unwrap_or_default()
+    }
+}
+
+impl Renderer for MemoryRenderer {
+    fn draw(&mut self, player_id: PlayerId, role: Role, state: &GameState) -> io::Result<()> {
+        self.frame = Some(compose(player_id, role, state));
+        Ok(())
+    }
+}
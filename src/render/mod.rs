@@ -0,0 +1,146 @@
+pub mod memory;
+pub mod terminal;
+
+use std::io;
+
+use crate::game::{GameState, PlayerId, Role};
+
+pub use memory::MemoryRenderer;
+pub use terminal::TerminalRenderer;
+
+// the HUD below the map needs more room than the map itself
+const HUD_WIDTH: usize = 48;
+const HUD_LINES: usize = 4;
+
+/*
+Screen layout
++-------------+   <- map, sized to state.arena, y = 0 is the bottom row
+|  E          |
+|      P      |
++-------------+
+ stats                <- HUD
+ players online
+ message / game over
+ controls             <- what the keys do, hosts have more of them
+*/
+
+// something that can show a frame of the game to player_id, who plays as role
+pub trait Renderer {
+    fn draw(&mut self, player_id: PlayerId, role: Role, state: &GameState) -> io::Result<()>;
+}
+
+// a grid of characters, one per terminal cell
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellBuffer {
+    width: usize,
+    height: usize,
+    cells: Vec<char>,
+}
+
+impl CellBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self { width, height, cells: vec![' '; width * height] }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> char {
+        self.cells[y * self.width + x]
+    }
+
+    // out of bounds writes are dropped
+    pub fn set(&mut self, x: usize, y: usize, ch: char) {
+        if x < self.width && y < self.height {
+            self.cells[y * self.width + x] = ch;
+        }
+    }
+
+    // writes text left to right, cut off at the right edge
+    pub fn put_str(&mut self, x: usize, y: usize, text: &str) {
+        for (offset, ch) in text.chars().enumerate() {
+            self.set(x + offset, y, ch);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(' ');
+    }
+
+    // one line per row, trailing spaces trimmed
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for row in self.cells.chunks(self.width) {
+            let line: String = row.iter().collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+}
+
+// draws the map and the HUD for player_id into a fresh buffer
+// only the host is told about the keys only the host may use
+pub fn compose(player_id: PlayerId, role: Role, state: &GameState) -> CellBuffer {
+    let arena = state.arena;
+    let map_rows = arena.height + 2;
+    let mut buffer = CellBuffer::new(HUD_WIDTH.max(arena.width + 2), map_rows + HUD_LINES);
+
+    // border
+    let horizontal = format!("+{}+", "-".repeat(arena.width));
+    buffer.put_str(0, 0, &horizontal);
+    buffer.put_str(0, map_rows - 1, &horizontal);
+    for row in 1..=arena.height {
+        buffer.set(0, row, '|');
+        buffer.set(arena.width + 1, row, '|');
+    }
+
+    // world y grows upwards, screen rows grow downwards
+    let mut plot = |x: usize, y: usize, ch: char| {
+        if arena.contains(x, y) {
+            buffer.set(x + 1, arena.height - y, ch);
+        }
+    };
+
+    // other players first so our own 'P' always wins the cell
+    for (id, player) in &state.players {
+        if *id != player_id {
+            plot(player.x, player.y, 'O');
+        }
+    }
+    let me = state.players.get(&player_id);
+    if let Some(player) = me {
+        plot(player.x, player.y, 'P');
+    }
+    for enemy in &state.enemies {
+        plot(enemy.x, enemy.y, 'E');
+    }
+
+    let hud = map_rows;
+    if let Some(player) = me {
+        let stats = format!("Score: {}  HP: {}  X: {}", player.score, player.hp, player.x);
+        buffer.put_str(1, hud, &stats);
+    }
+    buffer.put_str(1, hud + 1, &format!("Players online: {}", state.players.len()));
+    let game_over = match role {
+        Role::Host => "Game Over! Press 'r' to restart or 'q' to quit",
+        Role::Player => "Game Over! Waiting for the host to restart",
+    };
+    if me.is_some_and(|player| player.game_over) {
+        buffer.put_str(1, hud + 2, game_over);
+    } else {
+        buffer.put_str(1, hud + 2, &state.message);
+    }
+    let controls = match role {
+        Role::Host => "a/d or arrows to move, q to quit, X: shutdown",
+        Role::Player => "a/d or arrows to move, q to quit",
+    };
+    buffer.put_str(1, hud + 3, controls);
+
+    buffer
+}
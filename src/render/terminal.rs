@@ -0,0 +1,76 @@
+use std::io::{self, Stdout, Write};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    execute, queue,
+    style::Print,
+    terminal::{
+        self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+
+use super::{compose, CellBuffer, Renderer};
+use crate::game::{GameState, PlayerId, Role};
+
+/*
+TerminalRenderer
+- takes over the terminal: alternate screen, raw mode, hidden cursor
+- keeps what is on screen in `front`, every frame only the cells that
+  differ from it get a cursor move + print
+- gives the terminal back when dropped
+*/
+pub struct TerminalRenderer {
+    stdout: Stdout,
+    front: Option<CellBuffer>,
+}
+
+impl TerminalRenderer {
+    pub fn new() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        Ok(Self { stdout, front: None })
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    fn draw(&mut self, player_id: PlayerId, role: Role, state: &GameState) -> io::Result<()> {
+        let back = compose(player_id, role, state);
+
+        // nothing to diff against (first frame, or the layout changed size)
+        let front = match self.front.take() {
+            Some(front) if front.width() == back.width() && front.height() == back.height() => front,
+            _ => {
+                queue!(self.stdout, Clear(ClearType::All))?;
+                // a cleared screen is all spaces, same as a new buffer
+                CellBuffer::new(back.width(), back.height())
+            }
+        };
+
+        // some ptys report 0x0, don't clip against that
+        let (columns, rows) = match terminal::size() {
+            Ok((columns, rows)) if columns > 0 && rows > 0 => (columns, rows),
+            _ => (u16::MAX, u16::MAX),
+        };
+        for y in 0..back.height().min(rows as usize) {
+            for x in 0..back.width().min(columns as usize) {
+                let ch = back.get(x, y);
+                if front.get(x, y) != ch {
+                    queue!(self.stdout, MoveTo(x as u16, y as u16), Print(ch))?;
+                }
+            }
+        }
+        self.stdout.flush()?;
+
+        self.front = Some(back);
+        Ok(())
+    }
+}
+
+impl Drop for TerminalRenderer {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
@@ -0,0 +1,58 @@
+// both ends refuse a peer that doesn't speak ALPN_GAME, and the client says why
+
+use std::sync::Arc;
+
+use quic::common::is_alpn_mismatch;
+use quic::config::TransportSettings;
+use quic::quic_client::{QuicClient, ServerTrust, Status};
+use quic::quic_server::{cert, load_server_tls, ServerTls};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use quinn::Endpoint;
+
+mod common;
+use common::Silent;
+
+const OTHER_ALPN: &[u8] = b"some-other-game/1";
+
+#[tokio::test]
+async fn server_refuses_another_protocol() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Silent));
+
+    let mut tls = ServerTrust::InsecureDev.tls_config(None, &Status::default()).unwrap();
+    tls.alpn_protocols = vec![OTHER_ALPN.to_vec()];
+    let mut endpoint = Endpoint::client("127.0.0.1:0".parse().unwrap()).unwrap();
+    endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls).unwrap())));
+
+    let error = endpoint.connect(server.local_addr().unwrap(), "localhost").unwrap().await.unwrap_err();
+    assert!(is_alpn_mismatch(&error), "{}", error);
+}
+
+#[tokio::test]
+async fn client_names_the_mismatch() {
+    let dir = tempfile::tempdir().unwrap();
+    let tls = ServerTls {
+        cert: dir.path().join("cert.pem"),
+        key: dir.path().join("key.pem"),
+        client_ca: None,
+    };
+    let (cert_pem, key_pem) = cert::generate_self_signed(&["localhost".to_string()], 1).unwrap();
+    cert::write_new_file(&tls.cert, &cert_pem, false, false).unwrap();
+    cert::write_new_file(&tls.key, &key_pem, false, true).unwrap();
+
+    // some other QUIC service on the port we were pointed at
+    let mut server_tls = load_server_tls(&tls).unwrap();
+    server_tls.alpn_protocols = vec![OTHER_ALPN.to_vec()];
+    let config = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(server_tls).unwrap()));
+    let endpoint = Endpoint::server(config, "127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = endpoint.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Some(incoming) = endpoint.accept().await {
+            let _ = incoming.await;
+        }
+    });
+
+    let mut client = QuicClient::new(TransportSettings::default()).unwrap();
+    let error = client.connect(addr, "localhost", &ServerTrust::InsecureDev, None).await.unwrap_err().to_string();
+    assert_eq!(error, format!("{} is not a runner game server, or runs an incompatible version (ALPN mismatch)", addr));
+}
@@ -0,0 +1,64 @@
+// fixtures shared by the tests that need a real loopback server
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use quic::config::TransportSettings;
+use quic::protocol::{ClientMessage, ServerMessage};
+use quic::quic_client::{QuicClient, ServerLink, ServerTrust};
+use quic::quic_server::{cert, ConnectionContext, MessageHandler, QuicServer, ServerTls};
+
+// never answers anything
+pub struct Silent;
+
+#[async_trait]
+impl MessageHandler for Silent {
+    async fn handle(&self, _conn: &ConnectionContext, _message: ClientMessage) -> Option<ServerMessage> {
+        None
+    }
+}
+
+// a server on a free loopback port with a throwaway certificate
+pub fn start_server(dir: &tempfile::TempDir, handler: Arc<dyn MessageHandler>) -> Arc<QuicServer> {
+    start_server_with_ca(dir, handler, None)
+}
+
+// the same, asking every client for a certificate signed by client_ca
+pub fn start_server_with_ca(dir: &tempfile::TempDir, handler: Arc<dyn MessageHandler>, client_ca: Option<PathBuf>) -> Arc<QuicServer> {
+    let tls = ServerTls {
+        cert: dir.path().join("cert.pem"),
+        key: dir.path().join("key.pem"),
+        client_ca,
+    };
+    let (cert_pem, key_pem) = cert::generate_self_signed(&["localhost".to_string()], 1).unwrap();
+    cert::write_new_file(&tls.cert, &cert_pem, false, false).unwrap();
+    cert::write_new_file(&tls.key, &key_pem, false, true).unwrap();
+
+    let bind = "127.0.0.1:0".parse().unwrap();
+    let server = Arc::new(QuicServer::new(bind, &tls, &TransportSettings::default(), handler).unwrap());
+
+    let accepting = Arc::clone(&server);
+    tokio::spawn(async move { accepting.accept_loop().await });
+    server
+}
+
+// a new client connected to server, with its control stream open
+pub async fn open_link(server: &QuicServer) -> (QuicClient, ServerLink) {
+    let mut client = QuicClient::new(TransportSettings::default()).unwrap();
+    let connection = client
+        .connect(server.local_addr().unwrap(), "localhost", &ServerTrust::InsecureDev, None)
+        .await
+        .unwrap();
+    let link = client.open_link(connection, Duration::ZERO).await.unwrap();
+    (client, link)
+}
+
+pub async fn next_message(link: &ServerLink) -> ServerMessage {
+    tokio::time::timeout(Duration::from_secs(5), link.next_message())
+        .await
+        .expect("no reply within 5s")
+        .expect("connection closed")
+}
@@ -0,0 +1,107 @@
+// the game rules from a config file, checked, with command line flags on top
+
+use std::path::PathBuf;
+
+use quic::config::{GameConfig, GameOverrides, MAX_ARENA_HEIGHT, MAX_ARENA_WIDTH, MAX_ENEMIES};
+use quic::game::Arena;
+
+fn write(dir: &tempfile::TempDir, name: &str, text: &str) -> PathBuf {
+    let path = dir.path().join(name);
+    std::fs::write(&path, text).unwrap();
+    path
+}
+
+fn rejection(config: GameConfig) -> String {
+    config.validate().unwrap_err()
+}
+
+#[test]
+fn toml_and_json_read_the_same() {
+    let dir = tempfile::tempdir().unwrap();
+    let toml = write(&dir, "game.toml", "tick_rate = 30\nmax_enemies = 5\n\n[arena]\nwidth = 20\nheight = 15\n");
+    let json = write(&dir, "game.json", r#"{ "tick_rate": 30, "max_enemies": 5, "arena": { "width": 20, "height": 15 } }"#);
+
+    let expected = GameConfig { tick_rate: 30, max_enemies: 5, arena: Arena::new(20, 15), ..GameConfig::default() };
+    assert_eq!(GameConfig::load(&toml).unwrap(), expected);
+    assert_eq!(GameConfig::load(&json).unwrap(), expected);
+}
+
+#[test]
+fn missing_keys_keep_their_defaults() {
+    let dir = tempfile::tempdir().unwrap();
+    assert_eq!(GameConfig::load(&write(&dir, "empty.toml", "")).unwrap(), GameConfig::default());
+
+    let config = GameConfig::load(&write(&dir, "hp.json", r#"{ "starting_hp": 3 }"#)).unwrap();
+    assert_eq!(config, GameConfig { starting_hp: 3, ..GameConfig::default() });
+}
+
+#[test]
+fn unreadable_or_invalid_files_name_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = dir.path().join("missing.toml");
+    let error = GameConfig::load(&missing).unwrap_err().to_string();
+    assert!(error.starts_with(&format!("Failed to read config {}", missing.display())), "{}", error);
+
+    let broken = write(&dir, "broken.toml", "tick_rate = \"fast\"\n");
+    let error = GameConfig::load(&broken).unwrap_err().to_string();
+    assert!(error.starts_with(&format!("Invalid config {}", broken.display())), "{}", error);
+
+    // parses, but the simulation can't run with it
+    let zero = write(&dir, "zero.toml", "tick_rate = 0\n");
+    assert_eq!(GameConfig::load(&zero).unwrap_err().to_string(), "tick_rate must be 1..=1000, got 0");
+}
+
+#[test]
+fn values_the_game_can_not_run_with_are_rejected() {
+    let default = GameConfig::default;
+    assert!(default().validate().is_ok());
+
+    assert_eq!(rejection(GameConfig { tick_rate: 1001, ..default() }), "tick_rate must be 1..=1000, got 1001");
+    assert_eq!(
+        rejection(GameConfig { enemy_step_interval_ms: 0, ..default() }),
+        "score_interval_ms and enemy_step_interval_ms must be above 0"
+    );
+    assert_eq!(rejection(GameConfig { arena: Arena::new(2, 12), ..default() }), "arena must be at least 3x3, got 2x12");
+    assert_eq!(rejection(GameConfig { starting_hp: 0, ..default() }), "starting_hp must be above 0");
+}
+
+#[test]
+fn arena_and_enemies_are_capped() {
+    let default = GameConfig::default;
+    let largest = GameConfig {
+        arena: Arena::new(MAX_ARENA_WIDTH, MAX_ARENA_HEIGHT),
+        max_enemies: MAX_ENEMIES,
+        ..default()
+    };
+    assert!(largest.validate().is_ok());
+
+    let too_wide = GameConfig { arena: Arena::new(usize::MAX, 12), ..default() };
+    assert_eq!(rejection(too_wide), format!("arena can be at most {}x{}, got {}x12", MAX_ARENA_WIDTH, MAX_ARENA_HEIGHT, usize::MAX));
+    let too_high = GameConfig { arena: Arena::new(13, MAX_ARENA_HEIGHT + 1), ..default() };
+    assert!(rejection(too_high).starts_with("arena can be at most"));
+
+    let swarm = GameConfig { max_enemies: MAX_ENEMIES + 1, ..default() };
+    assert_eq!(rejection(swarm), format!("max_enemies can be at most {}, got {}", MAX_ENEMIES, MAX_ENEMIES + 1));
+}
+
+#[test]
+fn flags_beat_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = GameConfig::load(&write(&dir, "game.toml", "tick_rate = 30\nstarting_hp = 50\n")).unwrap();
+
+    let overrides = GameOverrides { tick_rate: Some(120), arena_width: Some(40), ..GameOverrides::default() };
+    let config = overrides.apply(file).unwrap();
+    assert_eq!(config.tick_rate, 120);
+    assert_eq!(config.arena, Arena::new(40, GameConfig::default().arena.height));
+    // not given on the command line, the file's value stays
+    assert_eq!(config.starting_hp, 50);
+
+    // no flags, no change
+    assert_eq!(GameOverrides::default().apply(GameConfig::default()).unwrap(), GameConfig::default());
+}
+
+#[test]
+fn flags_are_checked_too() {
+    let overrides = GameOverrides { arena_height: Some(MAX_ARENA_HEIGHT * 10), ..GameOverrides::default() };
+    assert!(overrides.apply(GameConfig::default()).unwrap_err().starts_with("arena can be at most"));
+}
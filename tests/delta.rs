@@ -0,0 +1,174 @@
+// delta snapshots, what the server diffs against and what the client rebuilds
+
+use quic::game::delta::HISTORY_LEN;
+use quic::game::{GameState, Player, Snapshot, SnapshotBuffer, SnapshotHistory, StateDelta};
+
+fn at_tick(mut state: GameState, tick: u64) -> GameState {
+    state.tick = tick;
+    state
+}
+
+// enemy order isn't part of the state, apply() appends what between() saw as new
+fn assert_same(actual: &GameState, expected: &GameState) {
+    assert_eq!(actual.tick, expected.tick);
+    assert_eq!(actual.arena, expected.arena);
+    assert_eq!(actual.players, expected.players);
+    assert_eq!(actual.message, expected.message);
+
+    let mut actual_enemies = actual.enemies.clone();
+    let mut expected_enemies = expected.enemies.clone();
+    actual_enemies.sort_by_key(|enemy| enemy.id);
+    expected_enemies.sort_by_key(|enemy| enemy.id);
+    assert_eq!(actual_enemies, expected_enemies);
+}
+
+fn round_trip(base: &GameState, current: &GameState) -> StateDelta {
+    let delta = StateDelta::between(base, current);
+    assert_same(&delta.apply(base), current);
+    delta
+}
+
+// a base tick the server sends and the client got
+fn base_with_players(ids: &[usize]) -> GameState {
+    let mut state = at_tick(GameState::new(), 1);
+    for id in ids {
+        state.players.insert(*id, Player::spawn());
+    }
+    state
+}
+
+#[test]
+fn players_added_changed_and_removed_survive_a_round_trip() {
+    let base = base_with_players(&[1, 2, 3]);
+    let mut current = at_tick(base.clone(), 2);
+    current.players.get_mut(&1).unwrap().x += 1;
+    current.players.remove(&2);
+    current.players.insert(4, Player::spawn());
+
+    let delta = round_trip(&base, &current);
+    assert_eq!((delta.base_tick, delta.tick), (1, 2));
+    let mut changed: Vec<usize> = delta.players_changed.keys().copied().collect();
+    changed.sort();
+    // 3 didn't change, so it isn't sent
+    assert_eq!(changed, vec![1, 4]);
+    assert_eq!(delta.players_removed, vec![2]);
+}
+
+#[test]
+fn enemies_and_message_survive_a_round_trip() {
+    let base = base_with_players(&[1]);
+    let mut current = at_tick(base.clone(), 2);
+    let (moved, removed) = (current.enemies[0].id, current.enemies[1].id);
+    current.enemies[0].y -= 1;
+    current.enemies.retain(|enemy| enemy.id != removed);
+    current.spawn_enemy(4, 11);
+    current.message = "Game Over!".to_string();
+
+    let delta = round_trip(&base, &current);
+    assert_eq!(delta.enemies_added.len(), 1);
+    assert_eq!(delta.enemies_moved.len(), 1);
+    assert_eq!(delta.enemies_moved[0].0, moved);
+    assert_eq!(delta.enemies_removed, vec![removed]);
+    assert_eq!(delta.message.as_deref(), Some("Game Over!"));
+}
+
+#[test]
+fn nothing_changed_means_an_empty_delta() {
+    let base = base_with_players(&[1]);
+    let current = at_tick(base.clone(), 2);
+
+    let delta = round_trip(&base, &current);
+    assert!(delta.players_changed.is_empty());
+    assert!(delta.players_removed.is_empty());
+    assert!(delta.enemies_added.is_empty() && delta.enemies_moved.is_empty() && delta.enemies_removed.is_empty());
+    assert_eq!(delta.message, None);
+}
+
+#[test]
+fn full_snapshot_until_the_client_acks() {
+    let mut history = SnapshotHistory::default();
+    let state = base_with_players(&[1]);
+
+    for tick in 1..=3 {
+        assert!(matches!(history.snapshot_for(&at_tick(state.clone(), tick)), Snapshot::Full(_)));
+    }
+
+    history.ack(2);
+    match history.snapshot_for(&at_tick(state, 4)) {
+        Snapshot::Delta(delta) => assert_eq!((delta.base_tick, delta.tick), (2, 4)),
+        Snapshot::Full(_) => panic!("expected a delta against the acked tick"),
+    }
+}
+
+#[test]
+fn full_snapshot_once_the_acked_tick_is_forgotten() {
+    let mut history = SnapshotHistory::default();
+    let state = base_with_players(&[1]);
+    history.snapshot_for(&at_tick(state.clone(), 1));
+    history.ack(1);
+
+    // tick 1 is kept for the next HISTORY_LEN snapshots, then pushed out
+    let mut tick = 2;
+    while let Snapshot::Delta(delta) = history.snapshot_for(&at_tick(state.clone(), tick)) {
+        assert_eq!(delta.base_tick, 1);
+        tick += 1;
+    }
+    assert_eq!(tick, HISTORY_LEN as u64 + 2);
+}
+
+#[test]
+fn late_acks_never_move_the_base_back() {
+    let mut history = SnapshotHistory::default();
+    let state = base_with_players(&[1]);
+    for tick in 1..=5 {
+        history.snapshot_for(&at_tick(state.clone(), tick));
+    }
+
+    history.ack(4);
+    history.ack(2);
+    history.ack(3);
+    match history.snapshot_for(&at_tick(state, 6)) {
+        Snapshot::Delta(delta) => assert_eq!(delta.base_tick, 4),
+        Snapshot::Full(_) => panic!("expected a delta against the newest ack"),
+    }
+}
+
+#[test]
+fn client_rebuilds_deltas_against_what_it_received() {
+    let mut buffer = SnapshotBuffer::default();
+    let base = base_with_players(&[1]);
+    assert_same(buffer.receive(Snapshot::Full(base.clone())).unwrap(), &base);
+
+    let mut current = at_tick(base.clone(), 2);
+    current.players.get_mut(&1).unwrap().score = 3;
+    let rebuilt = buffer.receive(Snapshot::Delta(StateDelta::between(&base, &current))).unwrap();
+    assert_same(rebuilt, &current);
+    assert_same(buffer.latest().unwrap(), &current);
+}
+
+#[test]
+fn delta_against_a_base_the_client_lacks_is_dropped() {
+    let mut buffer = SnapshotBuffer::default();
+    let base = base_with_players(&[1]);
+    let current = at_tick(base.clone(), 2);
+    let delta = StateDelta::between(&base, &current);
+
+    // never got tick 1
+    assert!(buffer.receive(Snapshot::Delta(delta.clone())).is_none());
+
+    // a different tick doesn't help either
+    buffer.receive(Snapshot::Full(at_tick(base, 0)));
+    assert!(buffer.receive(Snapshot::Delta(delta)).is_none());
+    assert_eq!(buffer.latest().unwrap().tick, 0);
+}
+
+#[test]
+fn stale_snapshots_are_dropped() {
+    let mut buffer = SnapshotBuffer::default();
+    let state = base_with_players(&[1]);
+    buffer.receive(Snapshot::Full(at_tick(state.clone(), 5)));
+
+    assert!(buffer.receive(Snapshot::Full(at_tick(state.clone(), 4))).is_none());
+    assert!(buffer.receive(Snapshot::Full(at_tick(state, 5))).is_none());
+    assert_eq!(buffer.latest().unwrap().tick, 5);
+}
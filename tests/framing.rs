@@ -0,0 +1,81 @@
+// length prefixed frames over an in-memory stream
+
+use quic::framing::{read_frame, write_frame, FrameError, MAX_FRAME_SIZE};
+use tokio::io::{duplex, AsyncWriteExt};
+
+#[tokio::test]
+async fn frames_round_trip_in_order() {
+    let (mut send, mut recv) = duplex(64);
+    let payloads: Vec<Vec<u8>> = vec![b"hello".to_vec(), Vec::new(), vec![7; 1000]];
+
+    // bigger than the pipe, so the reader has to keep up while this writes
+    let written = payloads.clone();
+    let writer = tokio::spawn(async move {
+        for payload in &written {
+            write_frame(&mut send, payload).await.unwrap();
+        }
+    });
+
+    for payload in &payloads {
+        assert_eq!(read_frame(&mut recv, MAX_FRAME_SIZE).await.unwrap().as_ref(), Some(payload));
+    }
+    writer.await.unwrap();
+    // the writer is gone, and it stopped between frames
+    assert!(read_frame(&mut recv, MAX_FRAME_SIZE).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn end_of_stream_between_frames_is_clean() {
+    let (send, mut recv) = duplex(64);
+    drop(send);
+    assert!(read_frame(&mut recv, MAX_FRAME_SIZE).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn length_over_the_limit_is_refused_before_reading_the_payload() {
+    let (mut send, mut recv) = duplex(64);
+    send.write_all(&11u32.to_be_bytes()).await.unwrap();
+
+    match read_frame(&mut recv, 10).await {
+        Err(FrameError::Oversize { len, max }) => assert_eq!((len, max), (11, 10)),
+        other => panic!("expected Oversize, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn oversize_payload_is_never_written() {
+    let (mut send, mut recv) = duplex(64);
+    let payload = vec![0; MAX_FRAME_SIZE + 1];
+
+    match write_frame(&mut send, &payload).await {
+        Err(FrameError::Oversize { len, max }) => assert_eq!((len, max), (MAX_FRAME_SIZE + 1, MAX_FRAME_SIZE)),
+        other => panic!("expected Oversize, got {:?}", other),
+    }
+    drop(send);
+    assert!(read_frame(&mut recv, MAX_FRAME_SIZE).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn stream_ending_inside_the_length_is_partial() {
+    let (mut send, mut recv) = duplex(64);
+    send.write_all(&[0, 0]).await.unwrap();
+    drop(send);
+
+    match read_frame(&mut recv, MAX_FRAME_SIZE).await {
+        Err(FrameError::Partial { expected, received }) => assert_eq!((expected, received), (4, 2)),
+        other => panic!("expected Partial, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn stream_ending_inside_the_payload_is_partial() {
+    let (mut send, mut recv) = duplex(64);
+    send.write_all(&5u32.to_be_bytes()).await.unwrap();
+    send.write_all(b"abc").await.unwrap();
+    drop(send);
+
+    match read_frame(&mut recv, MAX_FRAME_SIZE).await {
+        Err(FrameError::Partial { expected, received }) => assert_eq!((expected, received), (5, 3)),
+        other => panic!("expected Partial, got {:?}", other),
+    }
+}
@@ -0,0 +1,216 @@
+// request/response through QuicServer's MessageHandler, over a real loopback connection
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use quic::config::GameConfig;
+use quic::game::Role;
+use quic::protocol::{self, ClientMessage, Codec, GameEvent, ServerMessage, PROTOCOL_VERSION};
+use quic::quic_client::JoinRequest;
+use quic::quic_server::{ConnectionContext, MessageHandler, QuicServer};
+
+mod common;
+use common::{next_message, open_link};
+
+// answers join, ping and chat the simplest way that still goes through every reply path
+struct Echo;
+
+#[async_trait]
+impl MessageHandler for Echo {
+    async fn handle(&self, conn: &ConnectionContext, message: ClientMessage) -> Option<ServerMessage> {
+        match message {
+            ClientMessage::Join { version, codecs, .. } => {
+                Some(protocol::handshake(version, &codecs, conn.id, Role::Player, &GameConfig::default()))
+            }
+            ClientMessage::Ping(nonce) => Some(ServerMessage::Pong(nonce)),
+            ClientMessage::Chat { text } => {
+                let name = format!("{:?}", conn.codec());
+                Some(ServerMessage::Event(GameEvent::Chat { player_id: conn.id, name, text }))
+            }
+            _ => None,
+        }
+    }
+}
+
+// tells the client about itself the moment it is welcomed, in the codec it sees then
+struct Greeter {
+    server: OnceLock<Arc<QuicServer>>,
+}
+
+#[async_trait]
+impl MessageHandler for Greeter {
+    async fn handle(&self, conn: &ConnectionContext, message: ClientMessage) -> Option<ServerMessage> {
+        match message {
+            ClientMessage::Join { version, codecs, .. } => {
+                Some(protocol::handshake(version, &codecs, conn.id, Role::Player, &GameConfig::default()))
+            }
+            _ => None,
+        }
+    }
+
+    async fn welcomed(&self, conn: &ConnectionContext) {
+        let name = format!("{:?}", conn.codec());
+        let event = ServerMessage::Event(GameEvent::PlayerJoined { player_id: conn.id, name });
+        let server = self.server.get().unwrap();
+        assert!(server.get_connection(&conn.id).unwrap().send_message(&event));
+    }
+}
+
+fn join_request(codecs: Vec<Codec>) -> JoinRequest {
+    JoinRequest { name: "tester".to_string(), codecs, admin_token: None, resume_token: None }
+}
+
+#[tokio::test]
+async fn join_then_ping_in_the_negotiated_codec() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Echo));
+
+    for codec in Codec::SUPPORTED {
+        let (_client, link) = open_link(&server).await;
+        let joined = link.join(&join_request(vec![*codec])).await.unwrap();
+        assert_eq!(link.codec(), *codec);
+
+        // the server has to have switched too, or the client couldn't read these
+        for nonce in 1..=3 {
+            link.send_message(&ClientMessage::Ping(nonce)).await.unwrap();
+        }
+        for nonce in 1..=3 {
+            assert!(matches!(next_message(&link).await, ServerMessage::Pong(n) if n == nonce));
+        }
+
+        let client = server.get_connection(&joined.player_id).unwrap();
+        assert_eq!(client.context.codec(), *codec);
+        assert_eq!(client.context.identity, None);
+    }
+}
+
+#[tokio::test]
+async fn handler_sees_the_connection_it_answers() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Echo));
+    let (_client, link) = open_link(&server).await;
+    let joined = link.join(&join_request(vec![Codec::Json])).await.unwrap();
+
+    link.send_message(&ClientMessage::Chat { text: "hi".to_string() }).await.unwrap();
+    match next_message(&link).await {
+        ServerMessage::Event(GameEvent::Chat { player_id, name, text }) => {
+            assert_eq!(player_id, joined.player_id);
+            assert_eq!(name, "Json");
+            assert_eq!(text, "hi");
+        }
+        other => panic!("expected the chat back, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn handshake_rejection_comes_back_as_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Echo));
+    let (_client, link) = open_link(&server).await;
+
+    let error = link.join(&join_request(Vec::new())).await.unwrap_err();
+    assert!(error.to_string().contains("no common codec"), "{}", error);
+}
+
+#[tokio::test]
+async fn other_protocol_versions_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Echo));
+    let (_client, link) = open_link(&server).await;
+
+    // what a newer client would send
+    let join = ClientMessage::Join {
+        version: PROTOCOL_VERSION + 1,
+        name: "from the future".to_string(),
+        codecs: vec![Codec::Json],
+        admin_token: None,
+        resume_token: None,
+    };
+    link.send_message(&join).await.unwrap();
+    match next_message(&link).await {
+        ServerMessage::Rejected { reason } => assert_eq!(
+            reason,
+            format!("protocol version mismatch: server speaks {}, client sent {}", PROTOCOL_VERSION, PROTOCOL_VERSION + 1)
+        ),
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+    // still on the handshake codec, nothing was negotiated
+    assert_eq!(link.codec(), Codec::HANDSHAKE);
+}
+
+#[tokio::test]
+async fn whatever_follows_the_welcome_comes_after_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let greeter = Arc::new(Greeter { server: OnceLock::new() });
+    let server = common::start_server(&dir, Arc::clone(&greeter) as Arc<dyn MessageHandler>);
+    let _ = greeter.server.set(Arc::clone(&server));
+
+    for codec in Codec::SUPPORTED {
+        let (_client, link) = open_link(&server).await;
+        let joined = link.join(&join_request(vec![*codec])).await.unwrap();
+        match next_message(&link).await {
+            ServerMessage::Event(GameEvent::PlayerJoined { player_id, name }) => {
+                assert_eq!(player_id, joined.player_id);
+                assert_eq!(name, format!("{:?}", codec));
+            }
+            other => panic!("expected the greeting, got {:?}", other),
+        }
+    }
+}
+
+// remembers everything that reached it
+#[derive(Default)]
+struct Recorder {
+    seen: Mutex<Vec<ClientMessage>>,
+}
+
+#[async_trait]
+impl MessageHandler for Recorder {
+    async fn handle(&self, _conn: &ConnectionContext, message: ClientMessage) -> Option<ServerMessage> {
+        self.seen.lock().unwrap().push(message);
+        None
+    }
+}
+
+async fn wait_for_message(recorder: &Recorder) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while recorder.seen.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("nothing reached the handler within 5s");
+}
+
+#[tokio::test]
+async fn only_acks_are_taken_from_datagrams() {
+    let dir = tempfile::tempdir().unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let server = common::start_server(&dir, Arc::clone(&recorder) as Arc<dyn MessageHandler>);
+    let (_client, link) = open_link(&server).await;
+
+    // the server reads datagrams once it has the control stream, which it sees with the first frame
+    link.send_message(&ClientMessage::Ping(1)).await.unwrap();
+    wait_for_message(&recorder).await;
+    recorder.seen.lock().unwrap().clear();
+
+    let join = ClientMessage::Join {
+        version: PROTOCOL_VERSION,
+        name: "sneaky".to_string(),
+        codecs: vec![Codec::Json],
+        admin_token: None,
+        resume_token: None,
+    };
+    let unreliable = [join, ClientMessage::Chat { text: "hi".to_string() }, ClientMessage::Restart, ClientMessage::Quit, ClientMessage::Ack(3)];
+    for message in &unreliable {
+        link.connection.send_datagram(Codec::HANDSHAKE.encode(message).into()).unwrap();
+    }
+
+    // the ack went last, give anything sent before it a moment too
+    wait_for_message(&recorder).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let seen = recorder.seen.lock().unwrap();
+    assert!(matches!(seen.as_slice(), [ClientMessage::Ack(3)]), "{:?}", seen);
+}
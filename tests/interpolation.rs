@@ -0,0 +1,89 @@
+// enemies sliding between the last two snapshots on the client
+
+use std::time::{Duration, Instant};
+
+use quic::game::{Enemy, EnemyId, GameState, Interpolator};
+
+fn snapshot(tick: u64, enemies: Vec<Enemy>) -> GameState {
+    let mut state = GameState::new();
+    state.tick = tick;
+    state.enemies = enemies;
+    state
+}
+
+fn enemy(id: EnemyId, x: usize, y: usize) -> Enemy {
+    Enemy { id, x, y }
+}
+
+fn enemies(interpolator: &Interpolator, now: Instant) -> Vec<Enemy> {
+    interpolator.sample(now).unwrap().enemies
+}
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+#[test]
+fn nothing_to_show_before_the_first_snapshot() {
+    assert!(Interpolator::default().sample(Instant::now()).is_none());
+}
+
+#[test]
+fn a_single_snapshot_is_shown_as_is() {
+    let start = Instant::now();
+    let mut interpolator = Interpolator::default();
+    interpolator.push(snapshot(1, vec![enemy(1, 4, 10)]), start);
+    assert_eq!(enemies(&interpolator, start + INTERVAL), vec![enemy(1, 4, 10)]);
+}
+
+#[test]
+fn enemies_slide_from_the_previous_snapshot_to_the_current_one() {
+    let start = Instant::now();
+    let mut interpolator = Interpolator::default();
+    interpolator.push(snapshot(1, vec![enemy(1, 2, 10)]), start);
+    interpolator.push(snapshot(2, vec![enemy(1, 6, 6)]), start + INTERVAL);
+    let arrived = start + INTERVAL;
+
+    // alpha 0 right when it arrives, half way after half the interval, there after a whole one
+    assert_eq!(enemies(&interpolator, arrived), vec![enemy(1, 2, 10)]);
+    assert_eq!(enemies(&interpolator, arrived + INTERVAL / 2), vec![enemy(1, 4, 8)]);
+    assert_eq!(enemies(&interpolator, arrived + INTERVAL), vec![enemy(1, 6, 6)]);
+    // and it stays there until the next one
+    assert_eq!(enemies(&interpolator, arrived + INTERVAL * 3), vec![enemy(1, 6, 6)]);
+}
+
+#[test]
+fn old_and_repeated_ticks_are_ignored() {
+    let start = Instant::now();
+    let mut interpolator = Interpolator::default();
+    interpolator.push(snapshot(5, vec![enemy(1, 2, 10)]), start);
+    interpolator.push(snapshot(6, vec![enemy(1, 6, 10)]), start + INTERVAL);
+
+    // late datagrams, they would move the enemy backwards or restart the slide
+    interpolator.push(snapshot(4, vec![enemy(1, 0, 10)]), start + INTERVAL * 2);
+    interpolator.push(snapshot(6, vec![enemy(1, 9, 10)]), start + INTERVAL * 2);
+
+    let sampled = interpolator.sample(start + INTERVAL * 2).unwrap();
+    assert_eq!(sampled.tick, 6);
+    assert_eq!(sampled.enemies, vec![enemy(1, 6, 10)]);
+}
+
+#[test]
+fn snapshots_arriving_together_are_not_slid() {
+    let start = Instant::now();
+    let mut interpolator = Interpolator::default();
+    interpolator.push(snapshot(1, vec![enemy(1, 2, 10)]), start);
+    interpolator.push(snapshot(2, vec![enemy(1, 6, 10)]), start);
+
+    // no interval to spread it over, straight to the current one
+    assert_eq!(enemies(&interpolator, start), vec![enemy(1, 6, 10)]);
+}
+
+#[test]
+fn new_enemies_pop_in_where_they_are() {
+    let start = Instant::now();
+    let mut interpolator = Interpolator::default();
+    interpolator.push(snapshot(1, vec![enemy(1, 2, 10)]), start);
+    interpolator.push(snapshot(2, vec![enemy(1, 6, 10), enemy(2, 9, 11)]), start + INTERVAL);
+
+    let sampled = enemies(&interpolator, start + INTERVAL + INTERVAL / 2);
+    assert_eq!(sampled, vec![enemy(1, 4, 10), enemy(2, 9, 11)]);
+}
@@ -0,0 +1,164 @@
+// connection bookkeeping in QuicServer, over a real loopback connection
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use quic::config::TransportSettings;
+use quic::quic_client::{QuicClient, ServerTrust};
+use quic::framing;
+use quic::quic_server::{ConnectionEvent, QuicServer, BACKLOG_CODE, CONTROL_BACKLOG, SHUTDOWN_CODE};
+use quinn::VarInt;
+use tokio::sync::broadcast;
+
+mod common;
+use common::Silent;
+
+async fn next_event(events: &mut broadcast::Receiver<ConnectionEvent>) -> ConnectionEvent {
+    tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("no connection event within 5s")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn closed_connections_leave_the_map() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Silent));
+    let mut events = server.subscribe();
+
+    let mut client = QuicClient::new(TransportSettings::default()).unwrap();
+    let connection = client
+        .connect(server.local_addr().unwrap(), "localhost", &ServerTrust::InsecureDev, None)
+        .await
+        .unwrap();
+
+    let ConnectionEvent::Connected { id, .. } = next_event(&mut events).await else {
+        panic!("expected Connected first");
+    };
+    assert!(server.connections.lock().unwrap().contains_key(&id));
+
+    connection.close(VarInt::from_u32(0), b"bye");
+
+    match next_event(&mut events).await {
+        ConnectionEvent::Disconnected { id: left, .. } => assert_eq!(left, id),
+        other => panic!("expected Disconnected, got {:?}", other),
+    }
+    assert!(server.connections.lock().unwrap().is_empty());
+    assert!(server.get_connection(&id).is_none());
+}
+
+#[tokio::test]
+async fn every_connection_gets_removed() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Silent));
+    let mut events = server.subscribe();
+    let addr = server.local_addr().unwrap();
+
+    let mut client = QuicClient::new(TransportSettings::default()).unwrap();
+    let mut connections = Vec::new();
+    for _ in 0..3 {
+        connections.push(client.connect(addr, "localhost", &ServerTrust::InsecureDev, None).await.unwrap());
+    }
+    for _ in 0..3 {
+        assert!(matches!(next_event(&mut events).await, ConnectionEvent::Connected { .. }));
+    }
+    assert_eq!(server.connections.lock().unwrap().len(), 3);
+
+    // the whole client going away closes all of them
+    client.endpoint.close(VarInt::from_u32(0), b"bye");
+    for _ in 0..3 {
+        assert!(matches!(next_event(&mut events).await, ConnectionEvent::Disconnected { .. }));
+    }
+    assert!(server.connections.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn shutdown_closes_every_client() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Silent));
+    let mut events = server.subscribe();
+
+    let mut client = QuicClient::new(TransportSettings::default()).unwrap();
+    let connection = client
+        .connect(server.local_addr().unwrap(), "localhost", &ServerTrust::InsecureDev, None)
+        .await
+        .unwrap();
+    assert!(matches!(next_event(&mut events).await, ConnectionEvent::Connected { .. }));
+
+    tokio::time::timeout(Duration::from_secs(10), server.shutdown(Duration::ZERO))
+        .await
+        .expect("shutdown didn't finish within 10s");
+
+    match connection.closed().await {
+        quinn::ConnectionError::ApplicationClosed(close) => assert_eq!(close.error_code, SHUTDOWN_CODE),
+        other => panic!("expected the server to close us, got {:?}", other),
+    }
+    assert!(server.connections.lock().unwrap().is_empty());
+}
+
+// a client that opened its control stream and then never reads from it
+struct Stalled {
+    _client: QuicClient,
+    connection: quinn::Connection,
+    // dropping them would reset the stream
+    _stream: (quinn::SendStream, quinn::RecvStream),
+    id: usize,
+}
+
+async fn stalled_client(server: &QuicServer, events: &mut broadcast::Receiver<ConnectionEvent>) -> Stalled {
+    let mut client = QuicClient::new(TransportSettings::default()).unwrap();
+    let connection = client
+        .connect(server.local_addr().unwrap(), "localhost", &ServerTrust::InsecureDev, None)
+        .await
+        .unwrap();
+    let ConnectionEvent::Connected { id, .. } = next_event(events).await else {
+        panic!("expected Connected first");
+    };
+
+    // the server only sees the stream once something is on it
+    let (mut send, recv) = connection.open_bi().await.unwrap();
+    framing::write_frame(&mut send, b"{}").await.unwrap();
+    Stalled { _client: client, connection, _stream: (send, recv), id }
+}
+
+#[tokio::test]
+async fn client_that_stops_reading_gets_closed() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Silent));
+    let mut events = server.subscribe();
+    let stalled = stalled_client(&server, &mut events).await;
+
+    // way more than flow control and the backlog together can hold
+    let frame = vec![0; 16 * 1024];
+    let mut queued = 0;
+    while server.send_to(stalled.id, &frame).is_ok() {
+        queued += 1;
+        assert!(queued < 10 * CONTROL_BACKLOG, "the control queue never filled up");
+        tokio::task::yield_now().await;
+    }
+
+    match tokio::time::timeout(Duration::from_secs(5), stalled.connection.closed()).await.unwrap() {
+        quinn::ConnectionError::ApplicationClosed(close) => assert_eq!(close.error_code, BACKLOG_CODE),
+        other => panic!("expected a backlog close, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn oversized_state_never_piles_up() {
+    let dir = tempfile::tempdir().unwrap();
+    let server = common::start_server(&dir, Arc::new(Silent));
+    let mut events = server.subscribe();
+    let stalled = stalled_client(&server, &mut events).await;
+
+    // too big for any datagram, so each one would have to go on the control stream
+    let state = vec![0; 16 * 1024];
+    for _ in 0..10 * CONTROL_BACKLOG {
+        server.broadcast_state_each(|_| Some(state.clone()));
+        tokio::task::yield_now().await;
+    }
+
+    // state waits for an empty queue instead of filling it, so we never closed the client
+    let client = server.get_connection(&stalled.id).unwrap();
+    assert!(client.connection.close_reason().is_none());
+    assert!(stalled.connection.close_reason().is_none());
+}
@@ -0,0 +1,246 @@
+// GameHandler, what it answers right away and what it leaves in the Inbox
+
+use std::sync::{Arc, Mutex};
+
+use quic::config::GameConfig;
+use quic::game::{InputCommand, Role, Roles, Sessions};
+use quic::lobby::{GameHandler, Inbox, MAX_QUEUED_INPUTS};
+use quic::protocol::{ClientMessage, Codec, GameEvent, ServerMessage, MAX_CHAT_LEN, MAX_NAME_LEN, PROTOCOL_VERSION};
+use quic::quic_server::{ConnectionContext, MessageHandler};
+
+fn lobby(max_players: usize) -> (GameHandler, Arc<Mutex<Inbox>>) {
+    let config = GameConfig::default();
+    let inbox = Arc::new(Mutex::new(Inbox {
+        roles: Roles::new(None),
+        sessions: Sessions::new(config.resume_grace()),
+        ..Inbox::default()
+    }));
+    (GameHandler::new(Arc::clone(&inbox), config, max_players), inbox)
+}
+
+fn connection(id: usize) -> ConnectionContext {
+    ConnectionContext::new(id, "127.0.0.1:9000".parse().unwrap(), None)
+}
+
+fn join_message(name: &str) -> ClientMessage {
+    ClientMessage::Join {
+        version: PROTOCOL_VERSION,
+        name: name.to_string(),
+        codecs: vec![Codec::Json],
+        admin_token: None,
+        resume_token: None,
+    }
+}
+
+// what QuicServer does with a Join, minus the network
+async fn join(handler: &GameHandler, conn: &ConnectionContext, name: &str) {
+    let reply = handler.handle(conn, join_message(name)).await;
+    assert!(matches!(reply, Some(ServerMessage::Welcome { .. })), "{:?}", reply);
+    handler.welcomed(conn).await;
+}
+
+// joins on its own connection and hands back the resume token
+async fn join_with_token(handler: &GameHandler, conn: &ConnectionContext, name: &str) -> String {
+    let reply = handler.handle(conn, join_message(name)).await;
+    handler.welcomed(conn).await;
+    match reply {
+        Some(ServerMessage::Welcome { resume_token, .. }) => resume_token,
+        other => panic!("expected a Welcome, got {:?}", other),
+    }
+}
+
+fn rejection(reply: Option<ServerMessage>) -> String {
+    match reply {
+        Some(ServerMessage::Rejected { reason }) => reason,
+        other => panic!("expected a rejection, got {:?}", other),
+    }
+}
+
+fn chat(text: &str) -> ClientMessage {
+    ClientMessage::Chat { text: text.to_string() }
+}
+
+#[tokio::test]
+async fn game_messages_before_the_join_are_rejected() {
+    let (handler, inbox) = lobby(4);
+    let conn = connection(1);
+
+    for message in [ClientMessage::Input { seq: 1, command: InputCommand::MoveLeft }, chat("hi"), ClientMessage::Restart] {
+        let reason = rejection(handler.handle(&conn, message).await);
+        assert_eq!(reason, "Join before sending game messages");
+    }
+    // pings work without a join
+    assert!(matches!(handler.handle(&conn, ClientMessage::Ping(7)).await, Some(ServerMessage::Pong(7))));
+
+    let inbox = inbox.lock().unwrap();
+    assert!(inbox.inputs.is_empty() && inbox.chat.is_empty() && inbox.requests.is_empty());
+}
+
+#[tokio::test]
+async fn joined_only_after_the_welcome_is_queued() {
+    let (handler, inbox) = lobby(4);
+    let conn = connection(1);
+
+    handler.handle(&conn, join_message("alice")).await.unwrap();
+    assert!(inbox.lock().unwrap().joined.is_empty());
+    // still rejected, the client can't have seen its Welcome yet
+    rejection(handler.handle(&conn, chat("hi")).await);
+
+    handler.welcomed(&conn).await;
+    let inbox = inbox.lock().unwrap();
+    assert_eq!(inbox.joined.get(&1).map(String::as_str), Some("alice"));
+    assert!(inbox.pending.is_empty());
+}
+
+#[tokio::test]
+async fn handshakes_in_flight_count_towards_the_limit() {
+    let (handler, _inbox) = lobby(1);
+    handler.handle(&connection(1), join_message("alice")).await.unwrap();
+
+    let reason = rejection(handler.handle(&connection(2), join_message("bob")).await);
+    assert_eq!(reason, "Server is full (1 players)");
+}
+
+#[tokio::test]
+async fn chat_loses_its_control_characters() {
+    let (handler, inbox) = lobby(4);
+    let conn = connection(1);
+    join(&handler, &conn, "alice").await;
+
+    assert!(handler.handle(&conn, chat("  \x1b[2Jhi\n there\x07 ")).await.is_none());
+    // nothing left once they're gone, nothing to send
+    assert!(handler.handle(&conn, chat("\r\n\t")).await.is_none());
+
+    let chat = std::mem::take(&mut inbox.lock().unwrap().chat);
+    match chat.as_slice() {
+        [GameEvent::Chat { player_id, name, text }] => {
+            assert_eq!(*player_id, 1);
+            assert_eq!(name, "alice");
+            assert_eq!(text, "[2Jhi there");
+        }
+        other => panic!("expected one chat line, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn names_lose_their_control_characters_and_get_cut() {
+    let (handler, inbox) = lobby(4);
+    join(&handler, &connection(1), " \x1b]0;pwned\x07ev\x1b[31mil\n ").await;
+    join(&handler, &connection(2), &"é".repeat(MAX_NAME_LEN * 4)).await;
+    join(&handler, &connection(3), "\x1b\r\n").await;
+
+    let inbox = inbox.lock().unwrap();
+    assert_eq!(inbox.joined[&1], "]0;pwnedev[31mil");
+    assert_eq!(inbox.joined[&2], "é".repeat(MAX_NAME_LEN));
+    // nothing printable left
+    assert_eq!(inbox.joined[&3], "player");
+}
+
+#[tokio::test]
+async fn chat_goes_out_under_the_cleaned_name() {
+    let (handler, inbox) = lobby(4);
+    let conn = connection(1);
+    join(&handler, &conn, "\x1b[2Jmallory").await;
+
+    handler.handle(&conn, chat("hi")).await;
+    let chat = std::mem::take(&mut inbox.lock().unwrap().chat);
+    match chat.as_slice() {
+        [GameEvent::Chat { name, .. }] => assert_eq!(name, "[2Jmallory"),
+        other => panic!("expected one chat line, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn chat_is_limited_to_max_chat_len() {
+    let (handler, inbox) = lobby(4);
+    let conn = connection(1);
+    join(&handler, &conn, "alice").await;
+
+    let reason = rejection(handler.handle(&conn, chat(&"a".repeat(MAX_CHAT_LEN + 1))).await);
+    assert_eq!(reason, format!("Chat messages can be at most {} characters", MAX_CHAT_LEN));
+    assert!(inbox.lock().unwrap().chat.is_empty());
+
+    // the limit is in characters, and stripped ones don't count
+    let longest = format!("{}\x1b", "é".repeat(MAX_CHAT_LEN));
+    assert!(handler.handle(&conn, chat(&longest)).await.is_none());
+    assert_eq!(inbox.lock().unwrap().chat.len(), 1);
+}
+
+#[tokio::test]
+async fn queued_inputs_are_capped() {
+    let (handler, inbox) = lobby(4);
+    let conn = connection(1);
+    join(&handler, &conn, "alice").await;
+
+    for seq in 1..=(MAX_QUEUED_INPUTS as u64 * 2) {
+        handler.handle(&conn, ClientMessage::Input { seq, command: InputCommand::MoveRight }).await;
+    }
+
+    let queue = inbox.lock().unwrap().inputs.remove(&1).unwrap();
+    assert_eq!(queue.len(), MAX_QUEUED_INPUTS);
+    // the flood past the cap is what gets dropped
+    assert_eq!(queue.back().unwrap().seq, MAX_QUEUED_INPUTS as u64);
+}
+
+#[tokio::test]
+async fn host_that_never_finished_joining_hands_the_role_on() {
+    let (handler, inbox) = lobby(4);
+    // gets the host role, then drops before its Welcome is queued
+    handler.handle(&connection(1), join_message("alice")).await.unwrap();
+    join(&handler, &connection(2), "bob").await;
+
+    let new_hosts = inbox.lock().unwrap().drop_disconnected(&[2]);
+    assert_eq!(new_hosts, vec![2]);
+    let inbox = inbox.lock().unwrap();
+    assert!(inbox.pending.is_empty());
+    assert_eq!(inbox.roles.hosts().collect::<Vec<_>>(), vec![2]);
+}
+
+#[tokio::test]
+async fn nobody_left_means_the_next_joiner_hosts() {
+    let (handler, inbox) = lobby(4);
+    handler.handle(&connection(1), join_message("alice")).await.unwrap();
+    assert!(inbox.lock().unwrap().drop_disconnected(&[]).is_empty());
+
+    join(&handler, &connection(2), "bob").await;
+    assert_eq!(inbox.lock().unwrap().roles.role(2), Some(Role::Host));
+}
+
+#[tokio::test]
+async fn resumed_host_is_still_the_host() {
+    let (handler, inbox) = lobby(4);
+    let token = join_with_token(&handler, &connection(1), "alice").await;
+    join(&handler, &connection(2), "bob").await;
+
+    // alice's connection drops, she may still come back
+    assert!(inbox.lock().unwrap().drop_disconnected(&[2]).is_empty());
+    assert_eq!(inbox.lock().unwrap().roles.hosts().collect::<Vec<_>>(), vec![1]);
+
+    let mut resume = join_message("alice");
+    if let ClientMessage::Join { resume_token, .. } = &mut resume {
+        *resume_token = Some(token);
+    }
+    match handler.handle(&connection(3), resume).await {
+        Some(ServerMessage::Welcome { role, resumed, .. }) => assert_eq!((role, resumed), (Role::Host, true)),
+        other => panic!("expected a Welcome, got {:?}", other),
+    }
+    handler.welcomed(&connection(3)).await;
+
+    let mut inbox = inbox.lock().unwrap();
+    assert!(inbox.drop_disconnected(&[2, 3]).is_empty());
+    assert_eq!(inbox.roles.hosts().collect::<Vec<_>>(), vec![3]);
+    assert_eq!(inbox.roles.role(1), None);
+}
+
+#[tokio::test]
+async fn host_that_can_not_come_back_hands_the_role_on() {
+    let (handler, inbox) = lobby(4);
+    join(&handler, &connection(1), "alice").await;
+    join(&handler, &connection(2), "bob").await;
+
+    // what the tick loop does once alice quit or her grace ran out
+    let mut inbox = inbox.lock().unwrap();
+    inbox.sessions.forget(1);
+    assert_eq!(inbox.drop_disconnected(&[2]), vec![2]);
+    assert_eq!(inbox.roles.hosts().collect::<Vec<_>>(), vec![2]);
+}
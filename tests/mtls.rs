@@ -0,0 +1,115 @@
+// client certificates (mTLS), checked in real handshakes
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use quic::config::TransportSettings;
+use quic::protocol::{ClientMessage, ServerMessage};
+use quic::quic_client::{ClientIdentity, QuicClient, ServerTrust};
+use quic::quic_server::{cert, ConnectionContext, ConnectionEvent, MessageHandler, QuicServer};
+
+mod common;
+
+// answers pings, enough to prove the connection got through
+struct Pong;
+
+#[async_trait]
+impl MessageHandler for Pong {
+    async fn handle(&self, _conn: &ConnectionContext, message: ClientMessage) -> Option<ServerMessage> {
+        match message {
+            ClientMessage::Ping(nonce) => Some(ServerMessage::Pong(nonce)),
+            _ => None,
+        }
+    }
+}
+
+// a CA written to dir, returns its pem pair
+fn write_ca(dir: &Path, name: &str) -> (String, String) {
+    let (cert_pem, key_pem) = cert::generate_ca(name, 1).unwrap();
+    cert::write_new_file(&dir.join(format!("{}.pem", name)), &cert_pem, false, false).unwrap();
+    (cert_pem, key_pem)
+}
+
+// a client certificate for name signed by ca, written next to it
+fn write_client_cert(dir: &Path, ca: &(String, String), name: &str) -> ClientIdentity {
+    let (cert_pem, key_pem) = cert::issue_client_cert(&ca.0, &ca.1, name, 1).unwrap();
+    let identity = ClientIdentity {
+        cert: dir.join(format!("{}.pem", name)),
+        key: dir.join(format!("{}.key", name)),
+    };
+    cert::write_new_file(&identity.cert, &cert_pem, false, false).unwrap();
+    cert::write_new_file(&identity.key, &key_pem, false, true).unwrap();
+    identity
+}
+
+// a refused certificate may only show once the client tries to use the connection,
+// so this goes as far as a ping
+async fn ping(server: &QuicServer, identity: Option<&ClientIdentity>) -> Result<(), String> {
+    let attempt = async {
+        let mut client = QuicClient::new(TransportSettings::default()).map_err(|e| e.to_string())?;
+        let connection = client
+            .connect(server.local_addr().unwrap(), "localhost", &ServerTrust::InsecureDev, identity)
+            .await
+            .map_err(|e| e.to_string())?;
+        let link = client.open_link(connection, Duration::ZERO).await.map_err(|e| e.to_string())?;
+        link.send_message(&ClientMessage::Ping(1)).await.map_err(|e| e.to_string())?;
+        match link.next_message().await {
+            Some(ServerMessage::Pong(1)) => Ok(()),
+            Some(other) => Err(format!("expected a pong, got {:?}", other)),
+            None => Err(link.connection.closed().await.to_string()),
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), attempt).await.map_err(|_| "timed out".to_string())?
+}
+
+fn start_server(dir: &tempfile::TempDir) -> Arc<QuicServer> {
+    let client_ca = Some(dir.path().join("players.pem"));
+    common::start_server_with_ca(dir, Arc::new(Pong), client_ca)
+}
+
+#[tokio::test]
+async fn issued_certificate_gets_in_under_its_name() {
+    let dir = tempfile::tempdir().unwrap();
+    let ca = write_ca(dir.path(), "players");
+    let alice = write_client_cert(dir.path(), &ca, "alice");
+    let server = start_server(&dir);
+    let mut events = server.subscribe();
+
+    ping(&server, Some(&alice)).await.unwrap();
+
+    match events.recv().await.unwrap() {
+        ConnectionEvent::Connected { id, identity, .. } => {
+            assert_eq!(identity.as_deref(), Some("alice"));
+            let client = server.get_connection(&id).unwrap();
+            assert_eq!(client.context.identity.as_deref(), Some("alice"));
+        }
+        other => panic!("expected Connected, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn client_without_a_certificate_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    write_ca(dir.path(), "players");
+    let server = start_server(&dir);
+
+    let error = ping(&server, None).await.unwrap_err();
+    assert!(error.contains("peer sent no certificates"), "{}", error);
+    assert!(server.connections.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn certificate_from_another_ca_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    write_ca(dir.path(), "players");
+    // same name, wrong issuer
+    let stranger_ca = cert::generate_ca("strangers", 1).unwrap();
+    let mallory = write_client_cert(dir.path(), &stranger_ca, "alice");
+    let server = start_server(&dir);
+
+    let error = ping(&server, Some(&mallory)).await.unwrap_err();
+    assert!(error.contains("UnknownIssuer"), "{}", error);
+    assert!(server.connections.lock().unwrap().is_empty());
+}
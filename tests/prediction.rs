@@ -0,0 +1,75 @@
+// client side prediction of our own player, and reconciling it with the server
+
+use quic::game::{Arena, InputCommand, Player, Predictor};
+
+const ARENA: Arena = Arena::new(13, 12);
+
+fn at(x: usize, last_input_seq: u64) -> Player {
+    Player { x, last_input_seq, ..Player::spawn() }
+}
+
+#[test]
+fn inputs_before_the_first_snapshot_are_kept_for_later() {
+    let mut predictor = Predictor::new();
+    assert_eq!(predictor.input(InputCommand::MoveRight), 1);
+    assert_eq!(predictor.input(InputCommand::MoveRight), 2);
+    // nothing to predict on yet
+    assert!(predictor.predicted().is_none());
+
+    // the server hasn't seen either of them
+    predictor.reconcile(&at(5, 0), ARENA);
+    assert_eq!(predictor.predicted().unwrap().x, 7);
+}
+
+#[test]
+fn inputs_apply_right_away_once_there_is_a_snapshot() {
+    let mut predictor = Predictor::new();
+    predictor.reconcile(&at(5, 0), ARENA);
+
+    predictor.input(InputCommand::MoveLeft);
+    assert_eq!(predictor.predicted().unwrap().x, 4);
+    predictor.input(InputCommand::None);
+    assert_eq!(predictor.predicted().unwrap().x, 4);
+}
+
+#[test]
+fn reconcile_replays_only_what_the_server_has_not_applied() {
+    let mut predictor = Predictor::new();
+    predictor.reconcile(&at(5, 0), ARENA);
+    for _ in 0..4 {
+        predictor.input(InputCommand::MoveRight);
+    }
+    assert_eq!(predictor.predicted().unwrap().x, 9);
+
+    // the server applied 1 and 2 but put us somewhere else, 3 and 4 go on top
+    predictor.reconcile(&at(3, 2), ARENA);
+    assert_eq!(predictor.predicted().unwrap().x, 5);
+
+    // the dropped ones stay dropped
+    predictor.reconcile(&at(3, 2), ARENA);
+    assert_eq!(predictor.predicted().unwrap().x, 5);
+
+    // everything applied, the server's player is all there is
+    predictor.reconcile(&at(8, 4), ARENA);
+    assert_eq!(predictor.predicted().unwrap(), &at(8, 4));
+}
+
+#[test]
+fn predictions_stay_inside_the_arena() {
+    let mut predictor = Predictor::new();
+    predictor.reconcile(&at(ARENA.max_x(), 0), ARENA);
+    predictor.input(InputCommand::MoveRight);
+    assert_eq!(predictor.predicted().unwrap().x, ARENA.max_x());
+
+    // replays are clamped to the arena of the snapshot
+    let narrow = Arena::new(6, 12);
+    for _ in 0..3 {
+        predictor.input(InputCommand::MoveRight);
+    }
+    predictor.reconcile(&at(narrow.max_x(), 1), narrow);
+    assert_eq!(predictor.predicted().unwrap().x, narrow.max_x());
+
+    predictor.reconcile(&at(narrow.min_x(), 4), narrow);
+    predictor.input(InputCommand::MoveLeft);
+    assert_eq!(predictor.predicted().unwrap().x, narrow.min_x());
+}
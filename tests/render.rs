@@ -0,0 +1,177 @@
+// golden-file tests for what the client draws
+// UPDATE_GOLDEN=1 cargo test --test render rewrites the files from the current output
+
+use std::path::PathBuf;
+
+use quic::game::{Arena, GameState, InputCommand, Player, PlayerId, Role};
+use quic::render::{MemoryRenderer, Renderer};
+
+const ME: PlayerId = 1;
+const OTHER: PlayerId = 2;
+
+fn render(state: &GameState) -> String {
+    render_as(Role::Player, state)
+}
+
+fn render_as(role: Role, state: &GameState) -> String {
+    let mut renderer = MemoryRenderer::new();
+    renderer.draw(ME, role, state).unwrap();
+    renderer.frame()
+}
+
+fn assert_golden(name: &str, frame: &str) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", name].iter().collect();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, frame).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("missing golden file {}: {}", path.display(), e));
+    assert_eq!(frame, expected, "frame differs from {}", path.display());
+}
+
+// the state the tests start from: just us in the default arena, no enemies
+fn solo() -> GameState {
+    let mut state = GameState::new();
+    state.players.insert(ME, Player { score: 7, ..Player::spawn() });
+    state.enemies.clear();
+    state
+}
+
+#[test]
+fn empty_arena() {
+    assert_golden("empty.txt", &render(&GameState::new()));
+}
+
+#[test]
+fn player_and_borders() {
+    assert_golden("player.txt", &render(&solo()));
+}
+
+#[test]
+fn other_players_and_enemies() {
+    let mut state = solo();
+    state.players.insert(OTHER, Player { x: 9, ..Player::spawn() });
+    state.spawn_enemy(3, 2);
+    state.spawn_enemy(11, 4);
+    state.message = "Welcome!".to_string();
+
+    assert_golden("enemies.txt", &render(&state));
+}
+
+#[test]
+fn enemy_over_player() {
+    let mut state = solo();
+    state.spawn_enemy(5, 1);
+
+    assert_golden("collision.txt", &render(&state));
+}
+
+#[test]
+fn game_over_screen() {
+    let mut state = solo();
+    state.players.get_mut(&ME).unwrap().game_over = true;
+    state.message = "ignored while game over".to_string();
+
+    assert_golden("game_over.txt", &render(&state));
+}
+
+#[test]
+fn host_is_told_how_to_restart() {
+    let mut state = solo();
+    state.players.get_mut(&ME).unwrap().game_over = true;
+
+    assert_golden("game_over_host.txt", &render_as(Role::Host, &state));
+}
+
+#[test]
+fn only_the_host_sees_the_host_keys() {
+    let state = solo();
+    let (player, host) = (render(&state), render_as(Role::Host, &state));
+
+    assert!(!player.contains("X: shutdown"));
+    assert!(host.contains("X: shutdown"));
+    // the map is the same for both
+    assert_eq!(player.lines().take(14).collect::<Vec<_>>(), host.lines().take(14).collect::<Vec<_>>());
+}
+
+// the screen cell world (x, y) ends up in, inside the border
+fn cell(frame: &str, state: &GameState, x: usize, y: usize) -> char {
+    let row = state.arena.height - y;
+    frame.lines().nth(row).and_then(|line| line.chars().nth(x + 1)).unwrap_or(' ')
+}
+
+#[test]
+fn map_is_sized_to_the_arena() {
+    for arena in [Arena::default(), Arena::new(5, 3), Arena::new(40, 20)] {
+        let frame = render(&GameState::with_arena(arena));
+        let border = format!("+{}+", "-".repeat(arena.width));
+
+        let lines: Vec<&str> = frame.lines().collect();
+        assert_eq!(lines[0], border);
+        assert_eq!(lines[arena.height + 1], border);
+    }
+}
+
+#[test]
+fn every_in_bounds_enemy_is_drawn() {
+    let arena = Arena::default();
+    for y in 0..arena.height {
+        for x in 0..arena.width {
+            let mut state = GameState::with_arena(arena);
+            state.enemies.clear();
+            state.spawn_enemy(x, y);
+
+            let frame = render(&state);
+            assert_eq!(cell(&frame, &state, x, y), 'E', "enemy at ({}, {}) not drawn", x, y);
+        }
+    }
+}
+
+#[test]
+fn enemies_on_the_spawn_row_are_drawn() {
+    let mut state = solo();
+    let arena = state.arena;
+    state.enemies.clear();
+    for x in arena.min_x()..=arena.max_x() {
+        state.spawn_enemy(x, arena.spawn_y());
+    }
+
+    let frame = render(&state);
+    for x in arena.min_x()..=arena.max_x() {
+        assert_eq!(cell(&frame, &state, x, arena.spawn_y()), 'E');
+    }
+}
+
+#[test]
+fn player_is_drawn_in_every_column_it_can_reach() {
+    let mut state = solo();
+    let arena = state.arena;
+    let me = state.players.get_mut(&ME).unwrap();
+    while me.x > arena.min_x() {
+        me.apply(InputCommand::MoveLeft, &arena);
+    }
+
+    loop {
+        let (x, y) = (state.players[&ME].x, state.players[&ME].y);
+        let frame = render(&state);
+        assert_eq!(cell(&frame, &state, x, y), 'P', "player at ({}, {}) not drawn", x, y);
+
+        if x == arena.max_x() {
+            break;
+        }
+        state.players.get_mut(&ME).unwrap().apply(InputCommand::MoveRight, &arena);
+    }
+}
+
+#[test]
+fn out_of_bounds_entities_are_dropped() {
+    let mut state = GameState::with_arena(Arena::new(5, 3));
+    state.enemies.clear();
+    state.spawn_enemy(5, 0);
+    state.spawn_enemy(0, 3);
+
+    assert!(!render(&state).contains('E'));
+}
@@ -0,0 +1,104 @@
+// getting a player back after the connection dropped
+
+use std::time::{Duration, Instant};
+
+use quic::game::{Player, Sessions};
+use quic::quic_client::Backoff;
+
+const GRACE: Duration = Duration::from_secs(30);
+
+#[test]
+fn parked_player_comes_back_with_its_token() {
+    let mut sessions = Sessions::new(GRACE);
+    let token = sessions.issue(1);
+    let mut player = Player::spawn();
+    player.score = 42;
+
+    let now = Instant::now();
+    assert!(sessions.park(1, player.clone(), now));
+    assert!(sessions.expire(now + GRACE / 2).is_empty());
+
+    assert_eq!(sessions.claim(&token), Some(1));
+    assert_eq!(sessions.take(1), Some(player));
+    // tokens work once
+    assert_eq!(sessions.claim(&token), None);
+}
+
+#[test]
+fn drop_noticed_after_the_claim_still_parks() {
+    let mut sessions = Sessions::new(GRACE);
+    let token = sessions.issue(1);
+    let mut player = Player::spawn();
+    player.x = 3;
+
+    // the new connection's Join comes in before we saw the old one close
+    assert_eq!(sessions.claim(&token), Some(1));
+    assert!(sessions.park(1, player.clone(), Instant::now()));
+
+    assert_eq!(sessions.take(1), Some(player));
+    // handed over, nothing left to park or take
+    assert!(!sessions.park(1, Player::spawn(), Instant::now()));
+    assert_eq!(sessions.take(1), None);
+}
+
+#[test]
+fn live_player_claim_is_cleared_by_take() {
+    let mut sessions = Sessions::new(GRACE);
+    let token = sessions.issue(1);
+    assert_eq!(sessions.claim(&token), Some(1));
+
+    // still live, the server moves it over itself
+    assert_eq!(sessions.take(1), None);
+    // so the old connection closing later doesn't park anything
+    assert!(!sessions.park(1, Player::spawn(), Instant::now()));
+}
+
+#[test]
+fn parked_player_expires_after_the_grace() {
+    let mut sessions = Sessions::new(GRACE);
+    let token = sessions.issue(1);
+
+    let now = Instant::now();
+    assert!(sessions.park(1, Player::spawn(), now));
+    assert_eq!(sessions.expire(now + GRACE), vec![1]);
+
+    assert_eq!(sessions.claim(&token), None);
+    assert_eq!(sessions.take(1), None);
+}
+
+#[test]
+fn new_token_replaces_the_old_one() {
+    let mut sessions = Sessions::new(GRACE);
+    let first = sessions.issue(1);
+    let second = sessions.issue(1);
+    assert_ne!(first, second);
+
+    assert_eq!(sessions.claim(&first), None);
+    assert_eq!(sessions.claim(&second), Some(1));
+}
+
+#[test]
+fn nothing_to_park_without_a_token_or_grace() {
+    let now = Instant::now();
+
+    let mut sessions = Sessions::new(GRACE);
+    assert!(!sessions.park(1, Player::spawn(), now));
+
+    let mut sessions = Sessions::new(Duration::ZERO);
+    let token = sessions.issue(1);
+    assert!(!sessions.park(1, Player::spawn(), now));
+    assert_eq!(sessions.claim(&token), None);
+}
+
+#[test]
+fn backoff_doubles_up_to_the_max() {
+    let mut backoff = Backoff::new(Duration::from_millis(250), Duration::from_secs(1));
+    let delays: Vec<Duration> = (0..5).map(|_| backoff.next_delay()).collect();
+    assert_eq!(
+        delays,
+        [250, 500, 1000, 1000, 1000].map(Duration::from_millis).to_vec()
+    );
+
+    backoff.reset();
+    assert_eq!(backoff.next_delay(), Duration::from_millis(250));
+}
@@ -0,0 +1,83 @@
+// who gets to restart or shut down the game
+
+use quic::game::{Role, Roles};
+
+fn join(roles: &mut Roles, id: usize, token: Option<&str>) -> Result<Role, String> {
+    let role = roles.assign(id, token)?;
+    roles.insert(id, role);
+    Ok(role)
+}
+
+#[test]
+fn first_joiner_hosts() {
+    let mut roles = Roles::new(None);
+    assert_eq!(join(&mut roles, 1, None), Ok(Role::Host));
+    assert_eq!(join(&mut roles, 2, None), Ok(Role::Player));
+
+    assert!(roles.require_host(1, "restart the game").is_ok());
+    assert_eq!(roles.require_host(2, "restart the game"), Err("Only the host can restart the game".to_string()));
+    // never joined
+    assert!(roles.require_host(3, "restart the game").is_err());
+}
+
+#[test]
+fn host_role_moves_to_the_oldest_player() {
+    let mut roles = Roles::new(None);
+    for id in 1..=3 {
+        join(&mut roles, id, None).unwrap();
+    }
+
+    assert_eq!(roles.remove(3), None);
+    assert_eq!(roles.remove(1), Some(2));
+    assert_eq!(roles.role(2), Some(Role::Host));
+    assert_eq!(roles.remove(2), None);
+    assert_eq!(roles.hosts().count(), 0);
+
+    // an empty session gets a new first joiner
+    assert_eq!(join(&mut roles, 4, None), Ok(Role::Host));
+}
+
+#[test]
+fn only_the_token_makes_a_host() {
+    let mut roles = Roles::new(Some("secret".to_string()));
+    assert_eq!(join(&mut roles, 1, None), Ok(Role::Player));
+    assert_eq!(join(&mut roles, 2, Some("secret")), Ok(Role::Host));
+    assert_eq!(join(&mut roles, 3, Some("secret")), Ok(Role::Host));
+    assert_eq!(roles.assign(4, Some("guess")), Err("Wrong admin token".to_string()));
+
+    // token hosts aren't replaced by whoever is left
+    roles.remove(2);
+    roles.remove(3);
+    assert_eq!(roles.role(1), Some(Role::Player));
+    assert_eq!(roles.hosts().count(), 0);
+}
+
+#[test]
+fn joining_again_keeps_the_role() {
+    let mut roles = Roles::new(None);
+    join(&mut roles, 1, None).unwrap();
+    join(&mut roles, 2, None).unwrap();
+
+    assert_eq!(join(&mut roles, 2, None), Ok(Role::Player));
+    assert_eq!(join(&mut roles, 1, None), Ok(Role::Host));
+    assert_eq!(roles.hosts().collect::<Vec<_>>(), vec![1]);
+}
+
+#[test]
+fn resumed_players_keep_their_role_and_place() {
+    let mut roles = Roles::new(None);
+    for id in 1..=3 {
+        join(&mut roles, id, None).unwrap();
+    }
+
+    assert_eq!(roles.transfer(1, 4), Some(Role::Host));
+    assert_eq!(roles.transfer(2, 5), Some(Role::Player));
+    assert_eq!(roles.role(1), None);
+    assert_eq!(roles.hosts().collect::<Vec<_>>(), vec![4]);
+    // still the oldest after 4, so next in line
+    assert_eq!(roles.remove(4), Some(5));
+
+    // nothing to move
+    assert_eq!(roles.transfer(9, 6), None);
+    assert_eq!(roles.role(6), None);
+}
@@ -0,0 +1,196 @@
+// the server's game rules, driven with fixed dts like ManualClock does
+
+use std::time::Duration;
+
+use quic::config::GameConfig;
+use quic::game::{Enemy, GameState, InputCommand, PlayerInput, Simulation};
+
+const DT: Duration = Duration::from_millis(100);
+
+fn config() -> GameConfig {
+    GameConfig {
+        score_interval_ms: 1000,
+        enemy_step_interval_ms: 500,
+        max_enemies: 3,
+        starting_hp: 3,
+        ..GameConfig::default()
+    }
+}
+
+// a game with the given players spawned, nothing stepped yet
+fn start(seed: u64, players: &[usize]) -> (Simulation, GameState) {
+    let config = config();
+    let sim = Simulation::new(seed, &config);
+    let mut state = GameState::with_arena(config.arena);
+    for id in players {
+        sim.spawn_player(&mut state, *id);
+    }
+    (sim, state)
+}
+
+fn input(player: usize, seq: u64, command: InputCommand) -> PlayerInput {
+    PlayerInput { player, seq, command }
+}
+
+fn enemy_rows(state: &GameState) -> Vec<usize> {
+    state.enemies.iter().map(|enemy| enemy.y).collect()
+}
+
+#[test]
+fn same_seed_inputs_and_dts_give_the_same_game() {
+    let commands = [InputCommand::MoveLeft, InputCommand::MoveRight, InputCommand::None];
+    let run = || {
+        let (mut sim, mut state) = start(7, &[1, 2]);
+        for step in 0..300u64 {
+            let inputs = [
+                input(1, step + 1, commands[step as usize % 3]),
+                input(2, step + 1, commands[step as usize * 7 % 3]),
+            ];
+            // uneven dts, so the timers don't always line up with the steps
+            let dt = Duration::from_millis(40 + step % 5 * 30);
+            sim.step(&mut state, &inputs, dt);
+        }
+        state
+    };
+
+    let (first, second) = (run(), run());
+    assert_eq!(first.tick, 300);
+    assert_eq!(first.tick, second.tick);
+    assert_eq!(first.players, second.players);
+    assert_eq!(first.enemies, second.enemies);
+    assert_eq!(first.message, second.message);
+}
+
+#[test]
+fn score_goes_up_once_per_score_interval() {
+    let (mut sim, mut state) = start(1, &[1]);
+
+    // the timer has to run past the interval, reaching it isn't enough
+    for _ in 0..10 {
+        sim.step(&mut state, &[], DT);
+    }
+    assert_eq!(state.players[&1].score, 0);
+    sim.step(&mut state, &[], DT);
+    assert_eq!(state.players[&1].score, 1);
+
+    // and it starts over from there
+    for _ in 0..10 {
+        sim.step(&mut state, &[], DT);
+    }
+    assert_eq!(state.players[&1].score, 1);
+    sim.step(&mut state, &[], DT);
+    assert_eq!(state.players[&1].score, 2);
+}
+
+#[test]
+fn enemies_step_down_once_per_enemy_interval() {
+    let (mut sim, mut state) = start(1, &[1]);
+    assert_eq!(enemy_rows(&state), vec![9, 5]);
+
+    for _ in 0..5 {
+        sim.step(&mut state, &[], DT);
+    }
+    assert_eq!(enemy_rows(&state), vec![9, 5]);
+
+    sim.step(&mut state, &[], DT);
+    // both moved down a row and a new one came in on top to make max_enemies
+    let top = state.arena.spawn_y();
+    assert_eq!(enemy_rows(&state), vec![top, 8, 4]);
+}
+
+#[test]
+fn players_stay_between_min_x_and_max_x() {
+    let (mut sim, mut state) = start(1, &[1]);
+    let arena = state.arena;
+
+    let lefts: Vec<PlayerInput> = (1..=20).map(|seq| input(1, seq, InputCommand::MoveLeft)).collect();
+    sim.step(&mut state, &lefts, Duration::from_millis(1));
+    assert_eq!(state.players[&1].x, arena.min_x());
+
+    let rights: Vec<PlayerInput> = (21..=40).map(|seq| input(1, seq, InputCommand::MoveRight)).collect();
+    sim.step(&mut state, &rights, Duration::from_millis(1));
+    assert_eq!(state.players[&1].x, arena.max_x());
+}
+
+#[test]
+fn collision_ends_the_game_only_for_the_player_hit() {
+    let (mut sim, mut state) = start(1, &[1, 2]);
+    let spawn = state.players[&1].clone();
+    state.enemies = vec![Enemy { id: 100, x: spawn.x + 1, y: spawn.y }];
+
+    // 2 walks into the enemy, 1 stays put
+    sim.step(&mut state, &[input(2, 1, InputCommand::MoveRight)], DT);
+    assert!(!state.players[&1].game_over);
+    assert!(state.players[&2].game_over);
+    assert!(state.any_alive());
+
+    // a finished player can't move anymore
+    sim.step(&mut state, &[input(2, 2, InputCommand::MoveLeft)], DT);
+    assert_eq!(state.players[&2].x, spawn.x + 1);
+}
+
+#[test]
+fn nothing_runs_once_every_player_is_out() {
+    let (mut sim, mut state) = start(1, &[1]);
+    let spawn = state.players[&1].clone();
+    state.enemies = vec![Enemy { id: 100, x: spawn.x, y: spawn.y }];
+
+    sim.step(&mut state, &[], DT);
+    assert!(state.players[&1].game_over);
+    assert!(!state.any_alive());
+
+    // well past both intervals, the game is over so neither timer runs
+    for _ in 0..30 {
+        sim.step(&mut state, &[], DT);
+    }
+    assert_eq!(state.players[&1].score, 0);
+    assert_eq!(enemy_rows(&state), vec![spawn.y]);
+}
+
+#[test]
+fn restart_resets_the_timers_and_hp() {
+    let (mut sim, mut state) = start(1, &[1]);
+    for _ in 0..9 {
+        sim.step(&mut state, &[], DT);
+    }
+    let player = state.players.get_mut(&1).unwrap();
+    player.hp = 1;
+    player.game_over = true;
+    player.last_input_seq = 5;
+
+    sim.restart(&mut state);
+    let player = &state.players[&1];
+    assert_eq!(player.hp, 3);
+    assert!(!player.game_over);
+    // seqs keep counting across restarts
+    assert_eq!(player.last_input_seq, 5);
+    assert_eq!(enemy_rows(&state), vec![9, 5]);
+
+    // 900ms were on the clocks before, without the reset both would fire here
+    for _ in 0..5 {
+        sim.step(&mut state, &[], DT);
+    }
+    assert_eq!(state.players[&1].score, 0);
+    assert_eq!(enemy_rows(&state), vec![9, 5]);
+}
+
+#[test]
+fn inputs_at_or_below_the_last_seq_are_ignored() {
+    let (mut sim, mut state) = start(1, &[1]);
+    let x = state.players[&1].x;
+
+    sim.step(&mut state, &[input(1, 2, InputCommand::MoveRight)], DT);
+    assert_eq!(state.players[&1].x, x + 1);
+    assert_eq!(state.players[&1].last_input_seq, 2);
+
+    // a resend and a late one, both already covered by seq 2
+    let stale = [input(1, 2, InputCommand::MoveRight), input(1, 1, InputCommand::MoveRight)];
+    sim.step(&mut state, &stale, DT);
+    assert_eq!(state.players[&1].x, x + 1);
+
+    // in the same batch, the duplicate is skipped too
+    let batch = [input(1, 3, InputCommand::MoveLeft), input(1, 3, InputCommand::MoveLeft)];
+    sim.step(&mut state, &batch, DT);
+    assert_eq!(state.players[&1].x, x);
+    assert_eq!(state.players[&1].last_input_seq, 3);
+}
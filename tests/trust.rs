@@ -0,0 +1,118 @@
+// pinned fingerprints and the known hosts file, checked in real handshakes
+
+use std::path::Path;
+use std::sync::Arc;
+
+use quic::config::TransportSettings;
+use quic::quic_client::{QuicClient, ServerTrust};
+use quic::quic_server::{cert, QuicServer};
+
+mod common;
+use common::Silent;
+
+const FINGERPRINT: &str = "AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89:AB:CD:EF:01:23:45:67:89";
+
+// a server with its own throwaway certificate, and that certificate's fingerprint
+fn start_server(dir: &tempfile::TempDir) -> (Arc<QuicServer>, String) {
+    let server = common::start_server(dir, Arc::new(Silent));
+    let certs = cert::load_cert_chain(&dir.path().join("cert.pem")).unwrap();
+    (server, cert::fingerprint(&certs[0]))
+}
+
+async fn connect(server: &QuicServer, trust: &ServerTrust) -> Result<(), String> {
+    let mut client = QuicClient::new(TransportSettings::default()).unwrap();
+    client
+        .connect(server.local_addr().unwrap(), "localhost", trust, None)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn known_hosts(path: &Path, host: &str) -> ServerTrust {
+    ServerTrust::KnownHosts { path: path.to_path_buf(), host: host.to_string() }
+}
+
+#[test]
+fn fingerprints_are_normalised() {
+    let bare = FINGERPRINT.replace(':', "");
+    for text in [FINGERPRINT.to_string(), FINGERPRINT.to_lowercase(), bare.clone(), bare.to_lowercase()] {
+        assert_eq!(cert::parse_fingerprint(&text).as_deref(), Ok(FINGERPRINT), "{}", text);
+    }
+    // what the server prints parses back to itself
+    let printed = cert::fingerprint(b"any certificate bytes");
+    assert_eq!(cert::parse_fingerprint(&printed), Ok(printed));
+}
+
+#[test]
+fn malformed_fingerprints_are_refused() {
+    let bare = FINGERPRINT.replace(':', "");
+    for text in ["", &bare[..62], &format!("{}00", bare), &bare.replacen('A', "G", 1), "not a fingerprint"] {
+        let error = cert::parse_fingerprint(text).unwrap_err();
+        assert!(error.contains("is not a SHA-256 fingerprint"), "{}", error);
+    }
+}
+
+#[tokio::test]
+async fn pinned_fingerprint_has_to_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let (server, fingerprint) = start_server(&dir);
+
+    // pinned the way a user would paste it
+    let pasted = fingerprint.replace(':', "").to_lowercase();
+    connect(&server, &ServerTrust::Fingerprint(pasted)).await.unwrap();
+
+    let error = connect(&server, &ServerTrust::Fingerprint(FINGERPRINT.to_string())).await.unwrap_err();
+    assert!(error.contains("doesn't match the pinned"), "{}", error);
+    assert!(error.contains(&fingerprint), "{}", error);
+}
+
+#[tokio::test]
+async fn known_hosts_remembers_the_first_fingerprint() {
+    let dir = tempfile::tempdir().unwrap();
+    let (server, fingerprint) = start_server(&dir);
+    let path = dir.path().join("known_hosts");
+
+    // first use, written down
+    connect(&server, &known_hosts(&path, "game.example")).await.unwrap();
+    let written = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(written, format!("game.example {}\n", fingerprint));
+
+    // later, it matches and nothing is added
+    connect(&server, &known_hosts(&path, "game.example")).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), written);
+
+    // another host name is another entry
+    connect(&server, &known_hosts(&path, "other.example")).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+}
+
+#[tokio::test]
+async fn known_hosts_entries_are_normalised_too() {
+    let dir = tempfile::tempdir().unwrap();
+    let (server, fingerprint) = start_server(&dir);
+    let path = dir.path().join("known_hosts");
+
+    // edited by hand, lowercase and without colons
+    let entry = format!("game.example {}\n", fingerprint.replace(':', "").to_lowercase());
+    std::fs::write(&path, &entry).unwrap();
+
+    connect(&server, &known_hosts(&path, "game.example")).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), entry);
+}
+
+#[tokio::test]
+async fn changed_fingerprint_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let (server, fingerprint) = start_server(&dir);
+    let path = dir.path().join("known_hosts");
+
+    // the server used to have a different certificate
+    let entry = format!("game.example {}\n", FINGERPRINT);
+    std::fs::write(&path, &entry).unwrap();
+
+    let error = connect(&server, &known_hosts(&path, "game.example")).await.unwrap_err();
+    assert!(error.contains("server certificate for game.example changed"), "{}", error);
+    assert!(error.contains(&fingerprint), "{}", error);
+    // the user has to remove the old line themselves
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), entry);
+}